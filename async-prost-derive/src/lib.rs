@@ -0,0 +1,368 @@
+//! Derive macros for [async-prost](https://docs.rs/async-prost).
+//!
+//! - `#[derive(Framed)]` implements `Framed` for a newtype around a framed value (e.g.
+//!   `struct RequestFrame(Frame<Header, Body>)`) by delegating to the inner value.
+//! - `#[derive(ShallDecodeBody)]` implements `ShallDecodeBody` for a header, driven by the
+//!   `#[decode_body(...)]` attribute:
+//!   - `#[decode_body(field = "kind", matches = "1 | 2")]`: decode when the field matches the
+//!     pattern.
+//!   - `#[decode_body(with = "path::to::fn")]`: decode when `fn(&header)` returns true.
+//!   - no attribute: always decode the body.
+//! - `#[derive(MessageEnum)]` implements `prost::Message` and `Default` for an enum whose
+//!   variants each wrap a prost message, so one stream could carry many message types. Each
+//!   variant is encoded as a length-delimited field whose tag identifies the variant, which is
+//!   wire compatible with a protobuf `oneof`. Tags default to the variant position (starting
+//!   from 1) and could be set with `#[message_enum(tag = 5)]`, tags must be unique and valid
+//!   field numbers, from 1 to 536870911. The default value is the first variant with a default
+//!   message. Decoding fails if the buffer carries no field with a known tag, unknown fields are
+//!   skipped otherwise.
+
+#![deny(missing_docs)]
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{
+    parse_macro_input, spanned::Spanned, Attribute, Data, DeriveInput, Error, Fields, Lit, Meta,
+    NestedMeta, Path,
+};
+
+/// derive `Framed` for a newtype around a framed value
+#[proc_macro_derive(Framed)]
+pub fn derive_framed(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_framed(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// derive `ShallDecodeBody` for a header, see the crate docs for the `decode_body` attribute
+#[proc_macro_derive(ShallDecodeBody, attributes(decode_body))]
+pub fn derive_shall_decode_body(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_shall_decode_body(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// derive `prost::Message` for an enum of prost messages, see the crate docs for the details
+#[proc_macro_derive(MessageEnum, attributes(message_enum))]
+pub fn derive_message_enum(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_message_enum(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand_framed(input: DeriveInput) -> Result<TokenStream2, Error> {
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => {
+            return Err(Error::new(
+                input.span(),
+                "Framed can only be derived for a newtype struct",
+            ))
+        }
+    };
+
+    let (inner, construct, access) = match fields {
+        Fields::Unnamed(f) if f.unnamed.len() == 1 => {
+            (&f.unnamed[0].ty, quote!(Self(inner)), quote!(self.0))
+        }
+        Fields::Named(f) if f.named.len() == 1 => {
+            let name = f.named[0].ident.as_ref().unwrap();
+            (
+                &f.named[0].ty,
+                quote!(Self { #name: inner }),
+                quote!(self.#name),
+            )
+        }
+        _ => {
+            return Err(Error::new(
+                fields.span(),
+                "Framed can only be derived for a struct with exactly one field",
+            ))
+        }
+    };
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::async_prost::Framed for #name #ty_generics #where_clause {
+            fn decode(
+                buf: &[u8],
+                header_len: usize,
+            ) -> ::std::result::Result<Self, ::std::io::Error>
+            where
+                Self: ::std::default::Default,
+            {
+                let inner = <#inner as ::async_prost::Framed>::decode(buf, header_len)?;
+                Ok(#construct)
+            }
+
+            fn header_len(&self) -> ::std::result::Result<usize, ::std::io::Error> {
+                ::async_prost::Framed::header_len(&#access)
+            }
+
+            fn body_len(&self) -> ::std::result::Result<usize, ::std::io::Error> {
+                ::async_prost::Framed::body_len(&#access)
+            }
+
+            fn flags(&self) -> ::async_prost::FrameFlags {
+                ::async_prost::Framed::flags(&#access)
+            }
+
+            fn set_flags(&mut self, flags: ::async_prost::FrameFlags) {
+                ::async_prost::Framed::set_flags(&mut #access, flags)
+            }
+
+            fn encode<B>(&self, buf: &mut B) -> ::std::result::Result<(), ::std::io::Error>
+            where
+                B: ::async_prost::__private::BufMut,
+                Self: Sized,
+            {
+                ::async_prost::Framed::encode(&#access, buf)
+            }
+
+            fn encode_into(
+                self,
+                buf: &mut ::async_prost::WriteBuf,
+            ) -> ::std::result::Result<(), ::std::io::Error> {
+                ::async_prost::Framed::encode_into(#access, buf)
+            }
+        }
+    })
+}
+
+fn expand_shall_decode_body(input: DeriveInput) -> Result<TokenStream2, Error> {
+    let mut field: Option<syn::Ident> = None;
+    let mut pattern: Option<TokenStream2> = None;
+    let mut with: Option<Path> = None;
+
+    for attr in input
+        .attrs
+        .iter()
+        .filter(|a| a.path.is_ident("decode_body"))
+    {
+        let list = match attr.parse_meta()? {
+            Meta::List(list) => list,
+            meta => return Err(Error::new(meta.span(), "expected #[decode_body(...)]")),
+        };
+
+        for nested in list.nested {
+            let nv = match nested {
+                NestedMeta::Meta(Meta::NameValue(nv)) => nv,
+                other => return Err(Error::new(other.span(), "expected `key = \"value\"`")),
+            };
+            let value = match &nv.lit {
+                Lit::Str(s) => s,
+                lit => return Err(Error::new(lit.span(), "expected a string literal")),
+            };
+            if nv.path.is_ident("field") {
+                field = Some(value.parse()?);
+            } else if nv.path.is_ident("matches") {
+                pattern = Some(value.parse()?);
+            } else if nv.path.is_ident("with") {
+                with = Some(value.parse()?);
+            } else {
+                return Err(Error::new(
+                    nv.path.span(),
+                    "unknown decode_body attribute, expected `field`, `matches` or `with`",
+                ));
+            }
+        }
+    }
+
+    let body = match (field, pattern, with) {
+        (None, None, None) => quote!(true),
+        (None, None, Some(with)) => quote!(#with(self)),
+        (Some(field), Some(pattern), None) => quote!(::core::matches!(self.#field, #pattern)),
+        _ => {
+            return Err(Error::new(
+                input.span(),
+                "decode_body expects either `field` and `matches`, or `with`",
+            ))
+        }
+    };
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::async_prost::ShallDecodeBody for #name #ty_generics #where_clause {
+            fn shall_decode_body(&self) -> bool {
+                #body
+            }
+        }
+    })
+}
+
+/// the largest field number protobuf allows
+const MAX_TAG: u32 = (1 << 29) - 1;
+
+fn variant_tag(attrs: &[Attribute]) -> Result<Option<u32>, Error> {
+    let mut tag = None;
+    for attr in attrs.iter().filter(|a| a.path.is_ident("message_enum")) {
+        let list = match attr.parse_meta()? {
+            Meta::List(list) => list,
+            meta => return Err(Error::new(meta.span(), "expected #[message_enum(tag = N)]")),
+        };
+        for nested in list.nested {
+            match nested {
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("tag") => match nv.lit {
+                    Lit::Int(v) => tag = Some(v.base10_parse()?),
+                    lit => return Err(Error::new(lit.span(), "expected an integer tag")),
+                },
+                other => return Err(Error::new(other.span(), "expected `tag = N`")),
+            }
+        }
+    }
+    Ok(tag)
+}
+
+fn expand_message_enum(input: DeriveInput) -> Result<TokenStream2, Error> {
+    let data = match &input.data {
+        Data::Enum(data) if !data.variants.is_empty() => data,
+        _ => {
+            return Err(Error::new(
+                input.span(),
+                "MessageEnum can only be derived for a non-empty enum",
+            ))
+        }
+    };
+
+    let mut variants = Vec::with_capacity(data.variants.len());
+    for (i, variant) in data.variants.iter().enumerate() {
+        let ty = match &variant.fields {
+            Fields::Unnamed(f) if f.unnamed.len() == 1 => &f.unnamed[0].ty,
+            _ => {
+                return Err(Error::new(
+                    variant.span(),
+                    "MessageEnum variants must wrap exactly one message",
+                ))
+            }
+        };
+        let tag = variant_tag(&variant.attrs)?.unwrap_or(i as u32 + 1);
+        if tag == 0 {
+            return Err(Error::new(variant.span(), "tag 0 is not a valid field tag"));
+        }
+        if tag > MAX_TAG {
+            return Err(Error::new(
+                variant.span(),
+                format!("tag {} exceeds the largest field tag {}", tag, MAX_TAG),
+            ));
+        }
+        if variants.iter().any(|(_, _, t)| *t == tag) {
+            return Err(Error::new(
+                variant.span(),
+                format!("tag {} is used by more than one variant", tag),
+            ));
+        }
+        variants.push((&variant.ident, ty, tag));
+    }
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let prost = quote!(::async_prost::__private::prost);
+    let bytes = quote!(::async_prost::__private::bytes);
+
+    let (first, first_ty, _) = variants[0];
+    let encode = variants.iter().map(|(ident, _, tag)| {
+        quote!(Self::#ident(v) => #prost::encoding::message::encode(#tag, v, buf))
+    });
+    let encoded_len = variants.iter().map(|(ident, _, tag)| {
+        quote!(Self::#ident(v) => #prost::encoding::message::encoded_len(#tag, v))
+    });
+    let tags = variants.iter().map(|(_, _, tag)| tag);
+    let merge = variants.iter().map(|(ident, ty, tag)| {
+        quote! {
+            #tag => {
+                if !::core::matches!(self, Self::#ident(_)) {
+                    *self = Self::#ident(<#ty as ::core::default::Default>::default());
+                }
+                match self {
+                    Self::#ident(v) => #prost::encoding::message::merge(wire_type, v, buf, ctx),
+                    _ => ::core::unreachable!(),
+                }
+            }
+        }
+    });
+
+    Ok(quote! {
+        impl #impl_generics #prost::Message for #name #ty_generics #where_clause {
+            fn encode_raw<B>(&self, buf: &mut B)
+            where
+                B: #bytes::BufMut,
+            {
+                match self {
+                    #(#encode,)*
+                }
+            }
+
+            fn decode<B>(mut buf: B) -> ::core::result::Result<Self, #prost::DecodeError>
+            where
+                B: #bytes::Buf,
+            {
+                // an empty or unknown-only buffer carries no variant, don't take it as the first one
+                let mut message = <Self as ::core::default::Default>::default();
+                let mut known = false;
+                let ctx = #prost::encoding::DecodeContext::default();
+                while #bytes::Buf::has_remaining(&buf) {
+                    let (tag, wire_type) = #prost::encoding::decode_key(&mut buf)?;
+                    known |= ::core::matches!(tag, #(#tags)|*);
+                    #prost::Message::merge_field(&mut message, tag, wire_type, &mut buf, ctx.clone())?;
+                }
+                if !known {
+                    return ::core::result::Result::Err(#prost::DecodeError::new(
+                        "no known variant in message enum",
+                    ));
+                }
+                ::core::result::Result::Ok(message)
+            }
+
+            fn decode_length_delimited<B>(mut buf: B) -> ::core::result::Result<Self, #prost::DecodeError>
+            where
+                B: #bytes::Buf,
+            {
+                let len = #prost::encoding::decode_varint(&mut buf)? as usize;
+                if len > #bytes::Buf::remaining(&buf) {
+                    return ::core::result::Result::Err(#prost::DecodeError::new("buffer underflow"));
+                }
+                Self::decode(#bytes::Buf::take(&mut buf, len))
+            }
+
+            fn merge_field<B>(
+                &mut self,
+                tag: u32,
+                wire_type: #prost::encoding::WireType,
+                buf: &mut B,
+                ctx: #prost::encoding::DecodeContext,
+            ) -> ::core::result::Result<(), #prost::DecodeError>
+            where
+                B: #bytes::Buf,
+            {
+                match tag {
+                    #(#merge)*
+                    _ => #prost::encoding::skip_field(wire_type, tag, buf, ctx),
+                }
+            }
+
+            fn encoded_len(&self) -> usize {
+                match self {
+                    #(#encoded_len,)*
+                }
+            }
+
+            fn clear(&mut self) {
+                *self = ::core::default::Default::default();
+            }
+        }
+
+        impl #impl_generics ::core::default::Default for #name #ty_generics #where_clause {
+            fn default() -> Self {
+                Self::#first(<#first_ty as ::core::default::Default>::default())
+            }
+        }
+    })
+}
@@ -0,0 +1,105 @@
+use std::{
+    error::Error,
+    fmt, io,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use futures_core::{ready, Stream};
+use prost::Message;
+use prost_types::Any;
+
+use crate::{AsyncProstReader, BodyRegistry};
+
+/// map type urls of `google.protobuf.Any` to the decoders of the concrete types
+pub type AnyRegistry<T> = BodyRegistry<String, T>;
+
+/// error for an `Any` whose type url isn't registered, wrapped in an `io::Error` of
+/// `InvalidData` kind
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownTypeUrl(pub String);
+
+impl fmt::Display for UnknownTypeUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown type url: {}", self.0)
+    }
+}
+
+impl Error for UnknownTypeUrl {}
+
+/// pack a message into a `google.protobuf.Any` with the type url
+pub fn pack_any<M>(type_url: impl Into<String>, msg: &M) -> Any
+where
+    M: Message,
+{
+    Any {
+        type_url: type_url.into(),
+        value: msg.encode_to_vec(),
+    }
+}
+
+/// unpack a `google.protobuf.Any` with the decoder registered for its type url
+pub fn unpack_any<T>(registry: &AnyRegistry<T>, any: &Any) -> Result<T, io::Error> {
+    match registry.decode(&any.type_url, &any.value) {
+        Some(v) => Ok(v?),
+        None => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            UnknownTypeUrl(any.type_url.clone()),
+        )),
+    }
+}
+
+/// A wrapper around a stream of `google.protobuf.Any` that dispatches each value to the decoder
+/// registered for its type url
+#[derive(Debug)]
+pub struct AnyReader<S, T> {
+    stream: S,
+    registry: Arc<AnyRegistry<T>>,
+}
+
+impl<S, T> AnyReader<S, T> {
+    /// create a new any reader
+    pub fn new(stream: S, registry: Arc<AnyRegistry<T>>) -> Self {
+        Self { stream, registry }
+    }
+
+    /// gets a reference to the underlying stream
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// gets a mutable reference to the underlying stream
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    /// unwrap the `AnyReader`, returning the underlying stream
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+impl<R, D> AsyncProstReader<R, Any, D> {
+    /// dispatch the received `Any` values with the registry
+    pub fn dispatch_any<T>(self, registry: Arc<AnyRegistry<T>>) -> AnyReader<Self, T> {
+        AnyReader::new(self, registry)
+    }
+}
+
+impl<S, T> Stream for AnyReader<S, T>
+where
+    S: Stream<Item = Result<Any, io::Error>> + Unpin,
+{
+    type Item = Result<T, io::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let any = match ready!(Pin::new(&mut this.stream).poll_next(cx)) {
+            Some(Ok(any)) => any,
+            Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+            None => return Poll::Ready(None),
+        };
+        Poll::Ready(Some(unpack_any(&this.registry, &any)))
+    }
+}
@@ -0,0 +1,319 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_core::ready;
+use futures_sink::Sink;
+use prost::Message;
+
+use tokio::io::AsyncWrite;
+
+use crate::{
+    AsyncDestination, AsyncFlaggedFrameDestination, AsyncFrameDestination, AsyncProstWriter,
+    EncodedFrame, EncodedMessage, Framed, ProstWriterFor, SyncDestination,
+};
+
+/// default number of messages queued for a subscriber before it's considered slow
+pub const DEFAULT_SUBSCRIBER_CAPACITY: usize = 64;
+
+/// What `Broadcaster` does with a subscriber whose queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlowPeerPolicy {
+    /// drop the oldest queued message to make room for the new one
+    DropOldest,
+    /// remove the subscriber, it's reported by `Broadcaster::take_disconnected`
+    Disconnect,
+    /// hold the broadcast back until the subscriber catches up
+    Block,
+}
+
+/// Identifier of a subscriber of a `Broadcaster`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SubscriberId(u64);
+
+/// how a destination encodes a value once for all the subscribers
+#[doc(hidden)]
+pub trait EncodeOnce<T> {
+    type Encoded: Clone;
+
+    fn encode_once(item: &T) -> Result<Self::Encoded, io::Error>;
+}
+
+impl<T: Message> EncodeOnce<T> for AsyncDestination {
+    type Encoded = EncodedMessage<T>;
+
+    fn encode_once(item: &T) -> Result<Self::Encoded, io::Error> {
+        Ok(EncodedMessage::new(item))
+    }
+}
+
+impl<T: Message> EncodeOnce<T> for SyncDestination {
+    type Encoded = EncodedMessage<T>;
+
+    fn encode_once(item: &T) -> Result<Self::Encoded, io::Error> {
+        Ok(EncodedMessage::new(item))
+    }
+}
+
+impl<F: Framed> EncodeOnce<F> for AsyncFrameDestination {
+    type Encoded = EncodedFrame<F>;
+
+    fn encode_once(item: &F) -> Result<Self::Encoded, io::Error> {
+        EncodedFrame::new(item)
+    }
+}
+
+impl<F: Framed> EncodeOnce<F> for AsyncFlaggedFrameDestination {
+    type Encoded = EncodedFrame<F>;
+
+    fn encode_once(item: &F) -> Result<Self::Encoded, io::Error> {
+        EncodedFrame::new(item)
+    }
+}
+
+#[derive(Debug)]
+struct Subscriber<W, T, D, E> {
+    writer: AsyncProstWriter<W, T, D>,
+    queue: VecDeque<E>,
+    policy: SlowPeerPolicy,
+}
+
+impl<W, T, D, E> Subscriber<W, T, D, E>
+where
+    W: AsyncWrite + Unpin,
+    AsyncProstWriter<W, T, D>: ProstWriterFor<E>,
+{
+    /// write out the queue, ready once everything is flushed
+    fn poll_drive(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        loop {
+            ready!(self.writer.poll_flush_all(cx))?;
+            if self.queue.is_empty() {
+                return Poll::Ready(Ok(()));
+            }
+
+            // the writer buffer is empty, hand all queued messages over as one batch
+            while !self.queue.is_empty() {
+                ready!(self.writer.poll_ready_all(cx))?;
+                let item = self.queue.pop_front().unwrap();
+                self.writer.start_send_item(item)?;
+            }
+        }
+    }
+}
+
+/// A hub that sends every value to many `AsyncProstWriter`s, encoding it only once.
+///
+/// Each subscriber has a queue of messages waiting for its writer. Once the queue is full, the
+/// subscriber is handled according to its `SlowPeerPolicy`, so that a slow peer doesn't hold
+/// back the others unless asked to. Subscribers failing with an error are removed and reported
+/// by `take_disconnected`, the broadcast goes on with the others.
+#[derive(Debug)]
+pub struct Broadcaster<W, T, D>
+where
+    D: EncodeOnce<T>,
+{
+    subscribers: BTreeMap<SubscriberId, Subscriber<W, T, D, D::Encoded>>,
+    disconnected: Vec<(SubscriberId, io::Error)>,
+    capacity: usize,
+    next_id: u64,
+}
+
+impl<W, T, D> Default for Broadcaster<W, T, D>
+where
+    D: EncodeOnce<T>,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<W, T, D> Broadcaster<W, T, D>
+where
+    D: EncodeOnce<T>,
+{
+    /// create a broadcaster without subscribers
+    pub fn new() -> Self {
+        Self::with_capacity(DEFAULT_SUBSCRIBER_CAPACITY)
+    }
+
+    /// create a broadcaster queueing at most `capacity` messages per subscriber, at least one
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            subscribers: BTreeMap::new(),
+            disconnected: Vec::new(),
+            capacity: capacity.max(1),
+            next_id: 0,
+        }
+    }
+
+    /// add a subscriber, it receives the values sent from now on
+    pub fn subscribe(
+        &mut self,
+        writer: AsyncProstWriter<W, T, D>,
+        policy: SlowPeerPolicy,
+    ) -> SubscriberId {
+        let id = SubscriberId(self.next_id);
+        self.next_id += 1;
+        self.subscribers.insert(
+            id,
+            Subscriber {
+                writer,
+                queue: VecDeque::new(),
+                policy,
+            },
+        );
+        id
+    }
+
+    /// Remove a subscriber, returning its writer.
+    ///
+    /// Messages still queued for it are lost, flush the broadcaster before to deliver them.
+    pub fn unsubscribe(&mut self, id: SubscriberId) -> Option<AsyncProstWriter<W, T, D>> {
+        self.subscribers.remove(&id).map(|s| s.writer)
+    }
+
+    /// returns true if the subscriber is still subscribed
+    pub fn contains(&self, id: SubscriberId) -> bool {
+        self.subscribers.contains_key(&id)
+    }
+
+    /// number of subscribers
+    pub fn len(&self) -> usize {
+        self.subscribers.len()
+    }
+
+    /// returns true if there are no subscribers
+    pub fn is_empty(&self) -> bool {
+        self.subscribers.is_empty()
+    }
+
+    /// number of messages queued for the subscriber
+    pub fn queued(&self, id: SubscriberId) -> Option<usize> {
+        self.subscribers.get(&id).map(|s| s.queue.len())
+    }
+
+    /// take the subscribers removed for an error or for being too slow, with the reason
+    pub fn take_disconnected(&mut self) -> Vec<(SubscriberId, io::Error)> {
+        std::mem::take(&mut self.disconnected)
+    }
+
+    fn disconnect(&mut self, id: SubscriberId, err: io::Error) {
+        self.subscribers.remove(&id);
+        self.disconnected.push((id, err));
+    }
+}
+
+impl<W, T, D> Broadcaster<W, T, D>
+where
+    D: EncodeOnce<T>,
+    W: AsyncWrite + Unpin,
+    AsyncProstWriter<W, T, D>: ProstWriterFor<D::Encoded>,
+{
+    /// make progress on all subscribers, ready once all of them are flushed
+    fn poll_drive_all(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        let mut failed = Vec::new();
+        let mut pending = false;
+        for (id, sub) in self.subscribers.iter_mut() {
+            match sub.poll_drive(cx) {
+                Poll::Ready(Ok(())) => {}
+                Poll::Ready(Err(e)) => failed.push((*id, e)),
+                Poll::Pending => pending = true,
+            }
+        }
+        for (id, e) in failed {
+            self.disconnect(id, e);
+        }
+
+        if pending {
+            Poll::Pending
+        } else {
+            Poll::Ready(())
+        }
+    }
+}
+
+impl<W, T, D> Sink<T> for Broadcaster<W, T, D>
+where
+    D: EncodeOnce<T>,
+    W: AsyncWrite + Unpin,
+    AsyncProstWriter<W, T, D>: ProstWriterFor<D::Encoded>,
+{
+    type Error = io::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        let _ = this.poll_drive_all(cx);
+
+        // only blocking subscribers may hold the broadcast back
+        let capacity = this.capacity;
+        let blocked = this
+            .subscribers
+            .values()
+            .any(|s| s.policy == SlowPeerPolicy::Block && s.queue.len() >= capacity);
+        if blocked {
+            Poll::Pending
+        } else {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), Self::Error> {
+        let this = self.get_mut();
+        let encoded = D::encode_once(&item)?;
+
+        let capacity = this.capacity;
+        let mut lagging = Vec::new();
+        for (id, sub) in this.subscribers.iter_mut() {
+            if sub.queue.len() >= capacity {
+                match sub.policy {
+                    SlowPeerPolicy::DropOldest => {
+                        sub.queue.pop_front();
+                    }
+                    SlowPeerPolicy::Disconnect => {
+                        lagging.push(*id);
+                        continue;
+                    }
+                    // poll_ready makes sure there is room
+                    SlowPeerPolicy::Block => {}
+                }
+            }
+            sub.queue.push_back(encoded.clone());
+        }
+        for id in lagging {
+            this.disconnect(id, io::Error::other("subscriber is lagging behind"));
+        }
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        ready!(self.get_mut().poll_drive_all(cx));
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        ready!(this.poll_drive_all(cx));
+
+        let mut failed = Vec::new();
+        let mut pending = false;
+        for (id, sub) in this.subscribers.iter_mut() {
+            match sub.writer.poll_close_all(cx) {
+                Poll::Ready(Ok(())) => {}
+                Poll::Ready(Err(e)) => failed.push((*id, e)),
+                Poll::Pending => pending = true,
+            }
+        }
+        for (id, e) in failed {
+            this.disconnect(id, e);
+        }
+
+        if pending {
+            Poll::Pending
+        } else {
+            Poll::Ready(Ok(()))
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use std::{collections::VecDeque, io::IoSlice};
+
+use bytes::{buf::UninitSlice, Buf, BufMut, Bytes, BytesMut};
+
+/// payloads shorter than this are copied, as a separate chunk would cost more than the copy
+pub const INLINE_LIMIT: usize = 256;
+
+/// max number of chunks handed to a single `poll_write_vectored`
+pub(crate) const MAX_IO_SLICES: usize = 64;
+
+/// The buffer `AsyncProstWriter` encodes values into.
+///
+/// Everything written through `BufMut` (length prefixes, headers, encoded messages) is copied
+/// into a contiguous tail, while payloads handed over with `put_bytes` are kept by reference and
+/// written out with `poll_write_vectored` when the underlying writer supports it. So are large
+/// sources of `BufMut::put`, which is how prost encodes `bytes` fields of type `Bytes`.
+#[derive(Debug, Default)]
+pub struct WriteBuf {
+    chunks: VecDeque<Bytes>,
+    tail: BytesMut,
+    len: usize,
+}
+
+impl WriteBuf {
+    /// create an empty buffer
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// number of bytes in the buffer
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// returns true if the buffer is empty
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// append a payload without copying it, unless it's shorter than `INLINE_LIMIT`
+    pub fn put_bytes(&mut self, bytes: Bytes) {
+        if bytes.len() < INLINE_LIMIT {
+            self.put_slice(&bytes);
+            return;
+        }
+
+        self.seal_tail();
+        self.len += bytes.len();
+        self.chunks.push_back(bytes);
+    }
+
+    /// move everything from `other` to the end of this buffer, without copying
+    pub fn append(&mut self, other: WriteBuf) {
+        self.seal_tail();
+        self.len += other.len;
+        self.chunks.extend(other.chunks);
+        if !other.tail.is_empty() {
+            self.chunks.push_back(other.tail.freeze());
+        }
+    }
+
+    /// move the tail to the chunks, so that the next payload goes after it
+    fn seal_tail(&mut self) {
+        if !self.tail.is_empty() {
+            self.chunks.push_back(self.tail.split().freeze());
+        }
+    }
+
+    /// the first contiguous part of the buffer
+    pub(crate) fn front(&self) -> &[u8] {
+        self.chunks
+            .front()
+            .map(|c| &c[..])
+            .unwrap_or(&self.tail[..])
+    }
+
+    /// fill `dst` with the parts of the buffer, return the number of slices filled
+    pub(crate) fn io_slices<'a>(&'a self, dst: &mut [IoSlice<'a>]) -> usize {
+        let parts = self
+            .chunks
+            .iter()
+            .map(|c| &c[..])
+            .chain(Some(&self.tail[..]))
+            .filter(|c| !c.is_empty());
+        let mut n = 0;
+        for (slot, part) in dst.iter_mut().zip(parts) {
+            *slot = IoSlice::new(part);
+            n += 1;
+        }
+        n
+    }
+
+    /// drop the first `cnt` bytes, which have been written out
+    pub(crate) fn consume(&mut self, mut cnt: usize) {
+        assert!(cnt <= self.len, "cannot consume more than buffered");
+        self.len -= cnt;
+        while cnt > 0 {
+            match self.chunks.front_mut() {
+                Some(chunk) if chunk.len() <= cnt => {
+                    cnt -= chunk.len();
+                    self.chunks.pop_front();
+                }
+                Some(chunk) => {
+                    chunk.advance(cnt);
+                    cnt = 0;
+                }
+                None => {
+                    self.tail.advance(cnt);
+                    cnt = 0;
+                }
+            }
+        }
+    }
+}
+
+unsafe impl BufMut for WriteBuf {
+    fn remaining_mut(&self) -> usize {
+        self.tail.remaining_mut()
+    }
+
+    unsafe fn advance_mut(&mut self, cnt: usize) {
+        self.tail.advance_mut(cnt);
+        self.len += cnt;
+    }
+
+    fn chunk_mut(&mut self) -> &mut UninitSlice {
+        self.tail.chunk_mut()
+    }
+
+    fn put_slice(&mut self, src: &[u8]) {
+        self.tail.extend_from_slice(src);
+        self.len += src.len();
+    }
+
+    fn put<T>(&mut self, mut src: T)
+    where
+        T: Buf,
+        Self: Sized,
+    {
+        // `copy_to_bytes` of `Bytes` hands the payload over without copying
+        if src.remaining() >= INLINE_LIMIT {
+            let bytes = src.copy_to_bytes(src.remaining());
+            self.put_bytes(bytes);
+            return;
+        }
+
+        while src.has_remaining() {
+            let chunk = src.chunk();
+            let n = chunk.len();
+            self.put_slice(chunk);
+            src.advance(n);
+        }
+    }
+}
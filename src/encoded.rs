@@ -0,0 +1,154 @@
+use std::{fmt, io, marker::PhantomData};
+
+use bytes::{Bytes, BytesMut};
+use prost::Message;
+
+use crate::{frame::FrameLen, FrameFlags, Framed};
+
+/// A message encoded once, which can be sent to many writers without encoding it again.
+///
+/// Writers with the same item type accept it as is with `feed_item`, `send_item` and
+/// `item_sink`: the encoded bytes are shared, so sending a large message to many peers costs no
+/// copy either.
+pub struct EncodedMessage<T> {
+    bytes: Bytes,
+    msg: PhantomData<fn() -> T>,
+}
+
+impl<T> EncodedMessage<T>
+where
+    T: Message,
+{
+    /// encode the message
+    pub fn new(msg: &T) -> Self {
+        Self::from_bytes(msg.encode_to_vec().into())
+    }
+}
+
+impl<T> EncodedMessage<T> {
+    /// wrap bytes of an already encoded `T`, they are sent as is
+    pub fn from_bytes(bytes: Bytes) -> Self {
+        Self {
+            bytes,
+            msg: PhantomData,
+        }
+    }
+
+    /// the encoded message
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// length of the encoded message
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// returns true if the encoded message is empty
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// unwrap the encoded bytes
+    pub fn into_bytes(self) -> Bytes {
+        self.bytes
+    }
+
+    pub(crate) fn bytes(&self) -> &Bytes {
+        &self.bytes
+    }
+}
+
+impl<T> Clone for EncodedMessage<T> {
+    fn clone(&self) -> Self {
+        Self::from_bytes(self.bytes.clone())
+    }
+}
+
+impl<T> fmt::Debug for EncodedMessage<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EncodedMessage")
+            .field("len", &self.bytes.len())
+            .finish()
+    }
+}
+
+/// A frame encoded once, which can be sent to many framed writers without encoding it again,
+/// with `feed_item`, `send_item` and `item_sink`.
+///
+/// Besides the encoded header and body, it keeps their lengths and the flags, so writers only
+/// have to put the prefix in front of the shared bytes.
+pub struct EncodedFrame<F> {
+    frame_len: FrameLen,
+    flags: FrameFlags,
+    bytes: Bytes,
+    frame: PhantomData<fn() -> F>,
+}
+
+impl<F> EncodedFrame<F>
+where
+    F: Framed,
+{
+    /// encode the frame, fails if it's too large to be framed
+    pub fn new(frame: &F) -> Result<Self, io::Error> {
+        let frame_len = FrameLen::of(frame)?;
+        let mut buf = BytesMut::with_capacity(frame.header_len()? + frame.body_len()?);
+        frame.encode(&mut buf)?;
+        Ok(Self {
+            frame_len,
+            flags: frame.flags(),
+            bytes: buf.freeze(),
+            frame: PhantomData,
+        })
+    }
+}
+
+impl<F> EncodedFrame<F> {
+    /// flags of the frame
+    pub fn flags(&self) -> FrameFlags {
+        self.flags
+    }
+
+    /// the encoded header and body
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// length of the encoded header and body
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// returns true if the frame has neither header nor body
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub(crate) fn frame_len(&self) -> FrameLen {
+        self.frame_len
+    }
+
+    pub(crate) fn bytes(&self) -> &Bytes {
+        &self.bytes
+    }
+}
+
+impl<F> Clone for EncodedFrame<F> {
+    fn clone(&self) -> Self {
+        Self {
+            frame_len: self.frame_len,
+            flags: self.flags,
+            bytes: self.bytes.clone(),
+            frame: PhantomData,
+        }
+    }
+}
+
+impl<F> fmt::Debug for EncodedFrame<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EncodedFrame")
+            .field("flags", &self.flags)
+            .field("len", &self.bytes.len())
+            .finish()
+    }
+}
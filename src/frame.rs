@@ -1,56 +1,523 @@
-use bytes::BufMut;
+use byteorder::{ByteOrder, NetworkEndian};
+use bytes::{BufMut, Bytes};
 use core::fmt::Debug;
 use either::Either;
 use prost::Message;
-use std::io::{self};
+use std::{
+    io::{self},
+    ops::{BitOr, BitOrAssign},
+};
+
+use crate::{LazyBody, WriteBuf};
 
 #[derive(Debug)]
 /// Decoded frame from buffer
-pub struct Frame<H, T> {
+///
+/// A frame without body is a legit header-only frame, e.g. a control message. With frame flags
+/// enabled it's marked by `FrameFlags::HEADER_ONLY` and received with a `None` body. Without frame
+/// flags it can't be told from an empty body, so it's received as a body with an empty message.
+///
+/// Frames could be created with `Frame::new`, struct literals shall end with
+/// `..Default::default()` as fields may be added, e.g. `flags` and `trailer`.
+///
+/// If the trailer type is a message, the trailer is sent after the body, followed by its length
+/// whose top bit marks a present trailer, so an empty trailer is still received as `Some`.
+pub struct Frame<H, T, TR = NoTrailer> {
     /// header of the frame
     pub header: Option<H>,
     /// body of the frame
     pub body: Option<Either<Vec<u8>, T>>,
+    /// trailer of the frame, sent after the body
+    pub trailer: Option<TR>,
+    /// flags of the frame, only sent for streams with frame flags enabled
+    pub flags: FrameFlags,
+}
+
+impl<H, T, TR> Frame<H, T, TR> {
+    /// create a frame with the header and body, without trailer and flags
+    pub fn new(header: Option<H>, body: Option<Either<Vec<u8>, T>>) -> Self {
+        Self {
+            header,
+            body,
+            ..Default::default()
+        }
+    }
 }
 
-impl<H, T> Default for Frame<H, T> {
+impl<H, T, TR> Default for Frame<H, T, TR> {
     fn default() -> Self {
         Self {
             header: None,
             body: None,
+            trailer: None,
+            flags: FrameFlags::default(),
         }
     }
 }
 
+const TRAILER_LEN_SIZE: usize = 4;
+
+/// top bit of the trailer length, set if the frame carries a trailer, even an empty one
+const TRAILER_PRESENT: u32 = 0x8000_0000;
+
+/// trailer sent after the body of a frame
+///
+/// The methods are named apart from `prost::Message`, so they don't clash for messages used as
+/// trailers.
+pub trait Trailer: Debug + Send + Sync + Sized {
+    /// false if the frame doesn't carry a trailer section at all
+    const ENABLED: bool;
+
+    /// encoded length of the trailer
+    fn trailer_len(&self) -> usize;
+
+    /// encode the trailer
+    fn encode_trailer<B>(&self, buf: &mut B) -> Result<(), io::Error>
+    where
+        B: BufMut;
+
+    /// decode the trailer
+    fn decode_trailer(buf: &[u8]) -> Result<Self, io::Error>;
+}
+
+/// The trailer type of frames without trailer, keeps the wire format unchanged
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct NoTrailer;
+
+impl Trailer for NoTrailer {
+    const ENABLED: bool = false;
+
+    fn trailer_len(&self) -> usize {
+        0
+    }
+
+    fn encode_trailer<B>(&self, _buf: &mut B) -> Result<(), io::Error>
+    where
+        B: BufMut,
+    {
+        Ok(())
+    }
+
+    fn decode_trailer(_buf: &[u8]) -> Result<Self, io::Error> {
+        Ok(NoTrailer)
+    }
+}
+
+impl<M> Trailer for M
+where
+    M: Message + Default,
+{
+    const ENABLED: bool = true;
+
+    fn trailer_len(&self) -> usize {
+        Message::encoded_len(self)
+    }
+
+    fn encode_trailer<B>(&self, buf: &mut B) -> Result<(), io::Error>
+    where
+        B: BufMut,
+    {
+        Ok(Message::encode(self, buf)?)
+    }
+
+    fn decode_trailer(buf: &[u8]) -> Result<Self, io::Error> {
+        Ok(Message::decode(buf)?)
+    }
+}
+
+impl<H, T, TR> Frame<H, T, TR>
+where
+    TR: Trailer,
+{
+    /// length of the trailer section, including the trailer length
+    pub(crate) fn trailer_section_len(&self) -> usize {
+        if !TR::ENABLED {
+            return 0;
+        }
+        TRAILER_LEN_SIZE + self.trailer.as_ref().map(|t| t.trailer_len()).unwrap_or(0)
+    }
+
+    pub(crate) fn write_trailer<B>(&self, buf: &mut B) -> Result<(), io::Error>
+    where
+        B: BufMut,
+    {
+        if !TR::ENABLED {
+            return Ok(());
+        }
+        let len = match self.trailer.as_ref() {
+            Some(trailer) => {
+                trailer.encode_trailer(buf)?;
+                TRAILER_PRESENT | trailer.trailer_len() as u32
+            }
+            None => 0,
+        };
+        buf.put_u32(len);
+        Ok(())
+    }
+
+    /// split the trailer section from the end of the buffer, return the rest and the trailer
+    pub(crate) fn split_trailer(buf: &[u8]) -> Result<(&[u8], Option<TR>), io::Error> {
+        if !TR::ENABLED {
+            return Ok((buf, None));
+        }
+        if buf.len() < TRAILER_LEN_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "frame is too short for the trailer length",
+            ));
+        }
+        let (rest, len_buf) = buf.split_at(buf.len() - TRAILER_LEN_SIZE);
+        let len = NetworkEndian::read_u32(len_buf);
+        if len == 0 {
+            return Ok((rest, None));
+        }
+        let len = (len & !TRAILER_PRESENT) as usize;
+        if len > rest.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "trailer length exceeds the frame",
+            ));
+        }
+        let (rest, trailer_buf) = rest.split_at(rest.len() - len);
+        Ok((rest, Some(TR::decode_trailer(trailer_buf)?)))
+    }
+}
+
+impl<H, T, TR> Frame<H, T, TR>
+where
+    H: Message,
+    TR: Trailer,
+{
+    /// encode the frame into the writer's buffer, raw bodies are handed over without copying
+    pub(crate) fn encode_frame_into<F>(
+        mut self,
+        buf: &mut WriteBuf,
+        encode_body: F,
+    ) -> Result<(), io::Error>
+    where
+        F: FnOnce(T, &mut WriteBuf) -> Result<(), io::Error>,
+    {
+        if let Some(header) = self.header.as_ref() {
+            header.encode(buf)?;
+        }
+
+        match self.body.take() {
+            Some(Either::Left(v)) => buf.put_bytes(Bytes::from(v)),
+            Some(Either::Right(v)) => encode_body(v, buf)?,
+            None => {}
+        };
+
+        self.write_trailer(buf)
+    }
+}
+
+/// Flags byte sent after the length of the frame, see `for_async_framed_with_flags`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FrameFlags(u8);
+
+impl FrameFlags {
+    /// no more frames will be sent after this one
+    pub const END_STREAM: FrameFlags = FrameFlags(0x01);
+    /// the body of the frame is compressed
+    pub const COMPRESSED: FrameFlags = FrameFlags(0x02);
+    /// the frame shall be processed with priority
+    pub const PRIORITY: FrameFlags = FrameFlags(0x04);
+    /// the frame is a control frame, rather than data
+    pub const CONTROL: FrameFlags = FrameFlags(0x08);
+    /// the frame carries no body, set for the frames of this crate whose body is `None`
+    pub const HEADER_ONLY: FrameFlags = FrameFlags(0x10);
+    /// the header length is sent as a `u32` after the flags, set by the writer for headers longer
+    /// than `MAX_HEADER_LEN` and cleared by the reader
+    pub const LONG_HEADER: FrameFlags = FrameFlags(0x20);
+
+    /// create flags from the raw byte
+    pub const fn from_bits(bits: u8) -> Self {
+        Self(bits)
+    }
+
+    /// return the raw byte of the flags
+    pub const fn bits(&self) -> u8 {
+        self.0
+    }
+
+    /// returns true if no flag is set
+    pub const fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// returns true if all the given flags are set
+    pub const fn contains(&self, other: FrameFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// set the given flags
+    pub fn insert(&mut self, other: FrameFlags) {
+        self.0 |= other.0;
+    }
+
+    /// clear the given flags
+    pub fn remove(&mut self, other: FrameFlags) {
+        self.0 &= !other.0;
+    }
+
+    /// the flags with `HEADER_ONLY` set or cleared
+    pub(crate) fn with_header_only(mut self, header_only: bool) -> Self {
+        if header_only {
+            self.insert(Self::HEADER_ONLY);
+        } else {
+            self.remove(Self::HEADER_ONLY);
+        }
+        self
+    }
+}
+
+impl BitOr for FrameFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for FrameFlags {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
 /// indicate if we shall decode body or not
 pub trait ShallDecodeBody {
     /// return true if decode body is required
     fn shall_decode_body(&self) -> bool;
 }
 
+/// max length of an encoded header, it takes the top byte of the length prefix
+///
+/// With frame flags enabled, longer headers up to `MAX_LONG_HEADER_LEN` are sent with
+/// `FrameFlags::LONG_HEADER`.
+pub const MAX_HEADER_LEN: usize = 0xff;
+
+/// max length of an encoded header with frame flags enabled
+pub const MAX_LONG_HEADER_LEN: usize = 0x00ff_ffff;
+
+/// max length of an encoded body, it takes the lower 3 bytes of the length prefix
+pub const MAX_BODY_LEN: usize = 0x00ff_ffff;
+
 /// encode and decode for frame
+///
+/// Implementors provide `header_len` and `body_len`, the crate packs them into the length prefix.
+/// Frames still computing the packed length themselves could implement `LegacyFramed` instead.
 pub trait Framed: Debug + Send + Sync {
     /// decode header(if exists) and body
     fn decode(buf: &[u8], header_len: usize) -> Result<Self, io::Error>
     where
         Self: Default;
 
-    /// encoded length
-    fn encoded_len(&self) -> u32
+    /// encoded length of the header
+    fn header_len(&self) -> Result<usize, io::Error>;
+
+    /// encoded length of the body
+    fn body_len(&self) -> Result<usize, io::Error>;
+
+    /// flags of the frame, only sent for streams with frame flags enabled
+    fn flags(&self) -> FrameFlags {
+        FrameFlags::default()
+    }
+
+    /// set the flags received with the frame, frames with `FrameFlags::HEADER_ONLY` shall drop
+    /// the body decoded from the empty buffer
+    fn set_flags(&mut self, _flags: FrameFlags) {}
+
+    /// encode header and body, with length
+    fn encode<B>(&self, buf: &mut B) -> Result<(), io::Error>
     where
+        B: BufMut,
         Self: Sized;
 
-    /// encode header and body, with length
+    /// encode header and body into the writer's buffer, consuming the frame
+    ///
+    /// Defaults to `encode`. Implementations may hand large payloads over to the buffer with
+    /// `WriteBuf::put_bytes` instead of copying them.
+    fn encode_into(self, buf: &mut WriteBuf) -> Result<(), io::Error>
+    where
+        Self: Sized,
+    {
+        self.encode(buf)
+    }
+}
+
+/// Legacy encode and decode for frame, with the lengths packed by the implementor
+///
+/// Every `LegacyFramed` type is `Framed`, the header and body lengths are unpacked from
+/// `encoded_len`. New implementations shall implement `Framed` directly.
+pub trait LegacyFramed: Debug + Send + Sync {
+    /// decode header(if exists) and body
+    fn decode(buf: &[u8], header_len: usize) -> Result<Self, io::Error>
+    where
+        Self: Default;
+
+    /// encoded length, with header length in the top byte and body length in the rest
+    fn encoded_len(&self) -> u32;
+
+    /// encode header and body
     fn encode<B>(&self, buf: &mut B) -> Result<(), io::Error>
     where
         B: BufMut,
         Self: Sized;
 }
 
-impl<H, T> Framed for Frame<H, T>
+impl<F> Framed for F
+where
+    F: LegacyFramed,
+{
+    fn decode(buf: &[u8], header_len: usize) -> Result<Self, io::Error>
+    where
+        Self: Default,
+    {
+        LegacyFramed::decode(buf, header_len)
+    }
+
+    fn header_len(&self) -> Result<usize, io::Error> {
+        Ok((self.encoded_len() >> 24) as usize)
+    }
+
+    fn body_len(&self) -> Result<usize, io::Error> {
+        Ok((self.encoded_len() & MAX_BODY_LEN as u32) as usize)
+    }
+
+    fn encode<B>(&self, buf: &mut B) -> Result<(), io::Error>
+    where
+        B: BufMut,
+        Self: Sized,
+    {
+        LegacyFramed::encode(self, buf)
+    }
+}
+
+/// encoded length of the header of a frame
+pub(crate) fn encoded_header_len<H>(header: Option<&H>) -> usize
+where
+    H: Message,
+{
+    header.map(|h| h.encoded_len()).unwrap_or(0)
+}
+
+/// encoded length of the body of a frame, `body_len` gives the length of a decoded body
+pub(crate) fn encoded_body_len<T, F>(body: Option<&Either<Vec<u8>, T>>, body_len: F) -> usize
+where
+    F: FnOnce(&T) -> usize,
+{
+    match body {
+        Some(Either::Left(v)) => v.len(),
+        Some(Either::Right(v)) => body_len(v),
+        None => 0,
+    }
+}
+
+/// encode the header and the body of a frame, `encode_body` encodes a decoded body
+pub(crate) fn encode_parts<H, T, B, F>(
+    header: Option<&H>,
+    body: Option<&Either<Vec<u8>, T>>,
+    buf: &mut B,
+    encode_body: F,
+) -> Result<(), io::Error>
+where
+    H: Message,
+    B: BufMut,
+    F: FnOnce(&T, &mut B) -> Result<(), io::Error>,
+{
+    if let Some(header) = header {
+        header.encode(buf)?;
+    }
+
+    match body {
+        Some(Either::Left(v)) => {
+            buf.put(v.as_slice());
+        }
+        Some(Either::Right(v)) => {
+            encode_body(v, buf)?;
+        }
+        None => {}
+    };
+
+    Ok(())
+}
+
+/// header and body length of a frame, checked against the limits of the length prefix
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct FrameLen {
+    header: usize,
+    body: usize,
+}
+
+impl FrameLen {
+    /// lengths of the frame, a header longer than `MAX_HEADER_LEN` can only be sent with flags
+    pub(crate) fn of<F>(item: &F) -> Result<Self, io::Error>
+    where
+        F: Framed + ?Sized,
+    {
+        let header = item.header_len()?;
+        let body = item.body_len()?;
+        if header > MAX_LONG_HEADER_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("header length {} exceeds {}", header, MAX_LONG_HEADER_LEN),
+            ));
+        }
+        if body > MAX_BODY_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("body length {} exceeds {}", body, MAX_BODY_LEN),
+            ));
+        }
+
+        Ok(Self { header, body })
+    }
+
+    /// put the length prefix, followed by the flags if `flags` is set
+    ///
+    /// Nothing is written if the frame can't be prefixed.
+    pub(crate) fn put_prefix<B>(
+        &self,
+        buf: &mut B,
+        flags: Option<FrameFlags>,
+    ) -> Result<(), io::Error>
+    where
+        B: BufMut,
+    {
+        let long_header = self.header > MAX_HEADER_LEN;
+        match flags {
+            None if long_header => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "header length {} exceeds {}, longer headers need frame flags",
+                        self.header, MAX_HEADER_LEN
+                    ),
+                ))
+            }
+            None => buf.put_u32((self.header as u32) << 24 | self.body as u32),
+            Some(mut flags) if long_header => {
+                flags.insert(FrameFlags::LONG_HEADER);
+                buf.put_u32(self.body as u32);
+                buf.put_u8(flags.bits());
+                buf.put_u32(self.header as u32);
+            }
+            Some(mut flags) => {
+                flags.remove(FrameFlags::LONG_HEADER);
+                buf.put_u32((self.header as u32) << 24 | self.body as u32);
+                buf.put_u8(flags.bits());
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<H, T, TR> Framed for Frame<H, T, TR>
 where
     H: Message + ShallDecodeBody + Default,
     T: Message + Default,
+    TR: Trailer,
 {
     fn decode(buf: &[u8], header_len: usize) -> Result<Self, io::Error>
     where
@@ -67,7 +534,8 @@ where
             decode_body = true;
         }
 
-        let body_buf = &buf[header_len..];
+        let (body_buf, trailer) = Self::split_trailer(&buf[header_len..])?;
+        this.trailer = trailer;
         if decode_body {
             let msg = Message::decode(body_buf)?;
 
@@ -80,22 +548,86 @@ where
         Ok(this)
     }
 
-    fn encoded_len(&self) -> u32
+    fn flags(&self) -> FrameFlags {
+        self.flags.with_header_only(self.body.is_none())
+    }
+
+    fn set_flags(&mut self, flags: FrameFlags) {
+        if flags.contains(FrameFlags::HEADER_ONLY) {
+            self.body = None;
+        }
+        self.flags = flags;
+    }
+
+    fn header_len(&self) -> Result<usize, io::Error> {
+        Ok(encoded_header_len(self.header.as_ref()))
+    }
+
+    fn body_len(&self) -> Result<usize, io::Error> {
+        let body_len = encoded_body_len(self.body.as_ref(), |v| v.encoded_len());
+        Ok(body_len + self.trailer_section_len())
+    }
+
+    fn encode<B>(&self, buf: &mut B) -> Result<(), io::Error>
     where
+        B: BufMut,
         Self: Sized,
     {
-        let header_len = if let Some(header) = self.header.as_ref() {
-            header.encoded_len() as u8
+        encode_parts(self.header.as_ref(), self.body.as_ref(), buf, |v, buf| {
+            Ok(v.encode(buf)?)
+        })?;
+        self.write_trailer(buf)
+    }
+
+    fn encode_into(self, buf: &mut WriteBuf) -> Result<(), io::Error> {
+        self.encode_frame_into(buf, |v, buf| Ok(v.encode(buf)?))
+    }
+}
+
+impl<H, T, TR> Framed for Frame<H, LazyBody<T>, TR>
+where
+    H: Message + Default,
+    T: Message + Default,
+    TR: Trailer,
+{
+    fn decode(buf: &[u8], header_len: usize) -> Result<Self, io::Error>
+    where
+        Self: Default,
+    {
+        let header = if header_len > 0 {
+            H::decode(&buf[0..header_len])?
         } else {
-            0
-        };
-        let body_len = match self.body.as_ref() {
-            Some(Either::Left(v)) => v.len() as u32,
-            Some(Either::Right(v)) => v.encoded_len() as u32,
-            None => 0,
+            H::default()
         };
 
-        (header_len as u32) << 24 | body_len
+        // body is always kept raw, it will be decoded when accessed
+        let (body_buf, trailer) = Self::split_trailer(&buf[header_len..])?;
+        Ok(Self {
+            header: Some(header),
+            body: Some(Either::Right(LazyBody::from_raw(body_buf.to_vec()))),
+            trailer,
+            flags: FrameFlags::default(),
+        })
+    }
+
+    fn flags(&self) -> FrameFlags {
+        self.flags.with_header_only(self.body.is_none())
+    }
+
+    fn set_flags(&mut self, flags: FrameFlags) {
+        if flags.contains(FrameFlags::HEADER_ONLY) {
+            self.body = None;
+        }
+        self.flags = flags;
+    }
+
+    fn header_len(&self) -> Result<usize, io::Error> {
+        Ok(encoded_header_len(self.header.as_ref()))
+    }
+
+    fn body_len(&self) -> Result<usize, io::Error> {
+        let body_len = encoded_body_len(self.body.as_ref(), |v| v.encoded_len());
+        Ok(body_len + self.trailer_section_len())
     }
 
     fn encode<B>(&self, buf: &mut B) -> Result<(), io::Error>
@@ -103,20 +635,13 @@ where
         B: BufMut,
         Self: Sized,
     {
-        if let Some(header) = self.header.as_ref() {
-            header.encode(buf)?;
-        }
-
-        match self.body.as_ref() {
-            Some(Either::Left(v)) => {
-                buf.put(v.as_slice());
-            }
-            Some(Either::Right(v)) => {
-                v.encode(buf)?;
-            }
-            None => unreachable!(),
-        };
+        encode_parts(self.header.as_ref(), self.body.as_ref(), buf, |v, buf| {
+            Ok(v.encode(buf)?)
+        })?;
+        self.write_trailer(buf)
+    }
 
-        Ok(())
+    fn encode_into(self, buf: &mut WriteBuf) -> Result<(), io::Error> {
+        self.encode_frame_into(buf, |v, buf| Ok(v.encode_into(buf)?))
     }
 }
@@ -0,0 +1,157 @@
+use std::io;
+
+use crate::{
+    AsyncBatchDestination, AsyncDestination, AsyncFlaggedFrameDestination, AsyncFrameDestination,
+    SyncDestination,
+};
+
+/// What is sent before each value on the wire, see `FramingConfig`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Prefix {
+    /// nothing, like `SyncDestination`: values can be sent but not read back
+    None,
+    /// the size of the value, like `AsyncDestination`
+    #[default]
+    Length,
+    /// the size of a batch of length-delimited values, like `AsyncBatchDestination`
+    Batch,
+    /// the packed header and body sizes of a frame, like `AsyncFrameDestination`
+    Frame,
+}
+
+/// Framing chosen at runtime, e.g. from a config file, used by `ConfiguredDestination` and
+/// `ConfiguredFrameDestination`.
+///
+/// The marker destinations are presets of it, resolved at compile time:
+/// `FramingConfig::from(AsyncDestination)` describes the same wire format as `AsyncDestination`.
+/// Switching to another destination with a `for_*` method drops the framing set before, so a
+/// preset always comes with its default limits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FramingConfig {
+    /// what is sent before each value
+    pub prefix: Prefix,
+    /// whether a flags byte follows the size of each frame, only used by `Prefix::Frame`
+    pub flags: bool,
+    /// the largest value (or batch) accepted, in bytes, larger ones fail to be sent or read
+    pub max_len: usize,
+    /// the largest batch sent, in bytes, see `AsyncProstWriter::for_async_batched`
+    pub max_batch_bytes: usize,
+    /// the most values sent in a batch, see `AsyncProstWriter::for_async_batched`
+    pub max_batch_count: usize,
+}
+
+impl Default for FramingConfig {
+    fn default() -> Self {
+        Self {
+            prefix: Prefix::default(),
+            flags: false,
+            max_len: u32::MAX as usize,
+            max_batch_bytes: 64 * 1024,
+            max_batch_count: 64,
+        }
+    }
+}
+
+impl FramingConfig {
+    /// framing with the given prefix and the default limits
+    pub fn new(prefix: Prefix) -> Self {
+        Self {
+            prefix,
+            ..Self::default()
+        }
+    }
+
+    /// send a flags byte after the size of each frame
+    pub fn with_flags(mut self) -> Self {
+        self.flags = true;
+        self
+    }
+
+    /// set the largest value (or batch) accepted
+    pub fn with_max_len(mut self, max_len: usize) -> Self {
+        self.max_len = max_len;
+        self
+    }
+
+    /// set the limits of a batch
+    pub fn with_batch_limits(mut self, max_bytes: usize, max_count: usize) -> Self {
+        self.max_batch_bytes = max_bytes;
+        self.max_batch_count = max_count;
+        self
+    }
+
+    /// Check the limits, e.g. read from a config file, before framing values with them.
+    ///
+    /// Fails with `InvalidInput` if `max_len` is zero or, with `Prefix::Batch`, if a batch can't
+    /// hold a value or doesn't fit in its length prefix.
+    pub fn validate(&self) -> io::Result<()> {
+        let invalid = |msg: &str| Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+        if self.max_len == 0 {
+            return invalid("max len shall not be zero");
+        }
+        if self.prefix == Prefix::Batch {
+            if self.max_batch_count == 0 {
+                return invalid("batch count shall not be zero");
+            }
+            if self.max_batch_bytes == 0 {
+                return invalid("batch size shall not be zero");
+            }
+            if self.max_batch_bytes > u32::MAX as usize {
+                return invalid("batch size shall fit in the length prefix");
+            }
+        }
+        Ok(())
+    }
+
+    /// fail if a value (or batch) of `len` bytes exceeds `max_len`
+    pub(crate) fn check_len(&self, len: usize) -> io::Result<()> {
+        if len > self.max_len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} bytes exceed the limit of {}", len, self.max_len),
+            ));
+        }
+        Ok(())
+    }
+
+    /// the error of a prefix which the values can't be framed with
+    pub(crate) fn unsupported(&self) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "{:?} prefix isn't supported by this destination",
+                self.prefix
+            ),
+        )
+    }
+}
+
+impl From<SyncDestination> for FramingConfig {
+    fn from(_: SyncDestination) -> Self {
+        Self::new(Prefix::None)
+    }
+}
+
+impl From<AsyncDestination> for FramingConfig {
+    fn from(_: AsyncDestination) -> Self {
+        Self::new(Prefix::Length)
+    }
+}
+
+impl From<AsyncBatchDestination> for FramingConfig {
+    fn from(_: AsyncBatchDestination) -> Self {
+        Self::new(Prefix::Batch)
+    }
+}
+
+impl From<AsyncFrameDestination> for FramingConfig {
+    fn from(_: AsyncFrameDestination) -> Self {
+        Self::new(Prefix::Frame)
+    }
+}
+
+impl From<AsyncFlaggedFrameDestination> for FramingConfig {
+    fn from(_: AsyncFlaggedFrameDestination) -> Self {
+        Self::new(Prefix::Frame).with_flags()
+    }
+}
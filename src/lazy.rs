@@ -0,0 +1,117 @@
+use std::sync::OnceLock;
+
+use bytes::{BufMut, Bytes};
+use prost::{DecodeError, EncodeError, Message};
+
+use crate::WriteBuf;
+
+/// A frame body that keeps the raw bytes and only decodes them on first access.
+///
+/// If the body is never mutated, it is re-encoded from the raw bytes, so forwarding a frame
+/// costs no decode/encode round trip.
+#[derive(Debug)]
+pub struct LazyBody<T> {
+    raw: Option<Vec<u8>>,
+    decoded: OnceLock<T>,
+}
+
+impl<T> LazyBody<T> {
+    /// create a lazy body from an already decoded message
+    pub fn new(msg: T) -> Self {
+        let decoded = OnceLock::new();
+        let _ = decoded.set(msg);
+        Self { raw: None, decoded }
+    }
+
+    /// create a lazy body from raw encoded bytes
+    pub fn from_raw(raw: Vec<u8>) -> Self {
+        Self {
+            raw: Some(raw),
+            decoded: OnceLock::new(),
+        }
+    }
+
+    /// returns the raw bytes if the body hasn't been modified since it was received
+    pub fn raw(&self) -> Option<&[u8]> {
+        self.raw.as_deref()
+    }
+
+    /// returns true if the body has already been decoded
+    pub fn is_decoded(&self) -> bool {
+        self.decoded.get().is_some()
+    }
+}
+
+impl<T> LazyBody<T>
+where
+    T: Message + Default,
+{
+    /// decode the body (if not yet decoded) and return a reference to it
+    pub fn get(&self) -> Result<&T, DecodeError> {
+        if let Some(msg) = self.decoded.get() {
+            return Ok(msg);
+        }
+
+        // raw must exist if nothing was decoded yet
+        let raw = self.raw.as_deref().unwrap_or_default();
+        let _ = self.decoded.set(T::decode(raw)?);
+        Ok(self.decoded.get().unwrap())
+    }
+
+    /// decode the body (if not yet decoded) and return a mutable reference to it.
+    ///
+    /// Since the body may be modified, the raw bytes are dropped and the body will be encoded
+    /// from the decoded message from now on.
+    pub fn get_mut(&mut self) -> Result<&mut T, DecodeError> {
+        self.get()?;
+        self.raw = None;
+        Ok(self.decoded.get_mut().unwrap())
+    }
+
+    /// consume the lazy body and return the decoded message
+    pub fn into_inner(mut self) -> Result<T, DecodeError> {
+        self.get()?;
+        Ok(self.decoded.take().unwrap())
+    }
+
+    /// encoded length of the body
+    pub fn encoded_len(&self) -> usize {
+        match (self.raw.as_ref(), self.decoded.get()) {
+            (Some(raw), _) => raw.len(),
+            (None, Some(msg)) => msg.encoded_len(),
+            (None, None) => 0,
+        }
+    }
+
+    /// encode the body, using the raw bytes if the body was never modified
+    pub fn encode<B>(&self, buf: &mut B) -> Result<(), EncodeError>
+    where
+        B: BufMut,
+    {
+        match (self.raw.as_ref(), self.decoded.get()) {
+            (Some(raw), _) => {
+                buf.put(raw.as_slice());
+                Ok(())
+            }
+            (None, Some(msg)) => msg.encode(buf),
+            (None, None) => Ok(()),
+        }
+    }
+
+    /// encode the body into the writer's buffer, handing the raw bytes over without copying
+    pub(crate) fn encode_into(self, buf: &mut WriteBuf) -> Result<(), EncodeError> {
+        match self.raw {
+            Some(raw) => {
+                buf.put_bytes(Bytes::from(raw));
+                Ok(())
+            }
+            None => self.encode(buf),
+        }
+    }
+}
+
+impl<T> From<T> for LazyBody<T> {
+    fn from(msg: T) -> Self {
+        Self::new(msg)
+    }
+}
@@ -4,15 +4,55 @@
 
 #![deny(missing_docs)]
 
+#[cfg(feature = "any")]
+mod any;
+mod broadcast;
+mod buf;
+mod encoded;
 mod frame;
+mod framing;
+mod joined;
+mod lazy;
+mod metadata;
+mod priority;
 mod reader;
+#[cfg(feature = "reflect")]
+mod reflect;
 mod stream;
+mod typed;
 mod writer;
 
-pub use crate::frame::{Frame, Framed, ShallDecodeBody};
+#[cfg(feature = "any")]
+pub use crate::any::{pack_any, unpack_any, AnyReader, AnyRegistry, UnknownTypeUrl};
+pub use crate::broadcast::{
+    Broadcaster, EncodeOnce, SlowPeerPolicy, SubscriberId, DEFAULT_SUBSCRIBER_CAPACITY,
+};
+pub use crate::buf::{WriteBuf, INLINE_LIMIT};
+pub use crate::encoded::{EncodedFrame, EncodedMessage};
+pub use crate::frame::{
+    Frame, FrameFlags, Framed, LegacyFramed, NoTrailer, ShallDecodeBody, Trailer, MAX_BODY_LEN,
+    MAX_HEADER_LEN, MAX_LONG_HEADER_LEN,
+};
+pub use crate::framing::{FramingConfig, Prefix};
+pub use crate::joined::Joined;
+pub use crate::lazy::LazyBody;
+pub use crate::metadata::{Metadata, AUTHORIZATION, CONTENT_TYPE, REQUEST_ID};
+pub use crate::priority::{Priority, PriorityWriter};
 pub use crate::reader::AsyncProstReader;
-pub use crate::stream::AsyncProstStream;
-pub use crate::writer::{AsyncProstWriter, ProstWriterFor};
+#[cfg(feature = "reflect")]
+pub use crate::reflect::{DynamicBody, DynamicReader};
+pub use crate::stream::{AsyncProstStream, ReuniteError, SplitReader, SplitWriter};
+pub use crate::typed::{BodyRegistry, MessageType, RegisteredBody, TypedFrame};
+pub use crate::writer::{AsyncProstWriter, FlushPolicy, ItemSink, ProstWriterFor, WriteTimeout};
+
+#[cfg(feature = "derive")]
+pub use async_prost_derive::{Framed, MessageEnum, ShallDecodeBody};
+
+#[doc(hidden)]
+pub mod __private {
+    pub use bytes::{self, BufMut};
+    pub use prost;
+}
 
 /// A marker that indicates that the wrapping type is compatible with `AsyncProstReader` with Prost support.
 #[derive(Debug)]
@@ -22,6 +62,26 @@ pub struct AsyncDestination;
 #[derive(Debug)]
 pub struct AsyncFrameDestination;
 
+/// a marker that indicates that the wrapper type is compatible with `AsyncProstReader` with Framed
+/// support, with a flags byte sent after the size of each frame.
+#[derive(Debug)]
+pub struct AsyncFlaggedFrameDestination;
+
+/// a marker that indicates that the wrapper type is compatible with `AsyncProstReader` with batch
+/// support, several length-delimited values are sent in a frame prefixed by its size.
+#[derive(Debug)]
+pub struct AsyncBatchDestination;
+
+/// a marker that indicates that the wrapper type frames prost values as set by a `FramingConfig`
+/// at runtime.
+#[derive(Debug)]
+pub struct ConfiguredDestination;
+
+/// a marker that indicates that the wrapper type frames `Framed` values as set by a
+/// `FramingConfig` at runtime.
+#[derive(Debug)]
+pub struct ConfiguredFrameDestination;
+
 /// A marker that indicates that the wrapping type is compatible with stock `prost` receivers.
 #[derive(Debug)]
 pub struct SyncDestination;
@@ -33,10 +93,6 @@ mod tests {
     use bytes::Bytes;
     use futures::prelude::*;
     use prost::Message;
-    use tokio::{
-        io::AsyncWriteExt,
-        net::{TcpListener, TcpStream},
-    };
 
     #[derive(Clone, PartialEq, Message)]
     pub struct Event {
@@ -48,18 +104,14 @@ mod tests {
 
     #[tokio::test]
     async fn echo_message_should() {
-        let echo = TcpListener::bind("127.0.0.1:0").await.unwrap();
-        let addr = echo.local_addr().unwrap();
+        let (client, echo) = AsyncProstStream::<_, Event, Event, _>::pair(4096);
 
         tokio::spawn(async move {
-            let (stream, _) = echo.accept().await.unwrap();
-            let mut stream = AsyncProstStream::<_, Event, Event, _>::from(stream).for_async();
-            let (r, w) = stream.tcp_split();
+            let (r, w) = echo.for_async().split();
             r.forward(w).await.unwrap();
         });
 
-        let stream = TcpStream::connect(&addr).await.unwrap();
-        let mut client = AsyncProstStream::<_, Event, Event, _>::from(stream).for_async();
+        let mut client = client.for_async();
         let event = Event {
             id: Bytes::from_static(b"1234"),
             data: Bytes::from_static(b"hello world"),
@@ -78,37 +130,33 @@ mod tests {
 
     #[tokio::test]
     async fn echo_lots_of_messages_should_work() {
-        let echo = TcpListener::bind("127.0.0.1:0").await.unwrap();
-        let addr = echo.local_addr().unwrap();
+        let (client, echo) = AsyncProstStream::<_, Event, Event, _>::pair(4096);
 
         tokio::spawn(async move {
-            let (stream, _) = echo.accept().await.unwrap();
-            let mut stream = AsyncProstStream::<_, Event, Event, _>::from(stream).for_async();
-            let (r, w) = stream.tcp_split();
+            let (r, w) = echo.for_async().split();
             r.forward(w).await.unwrap();
         });
 
+        // far more than the buffer holds, so send from another task while reading the echoes
         let n = 81920usize;
-        let stream = TcpStream::connect(&addr).await.unwrap();
-        let mut client = AsyncProstStream::<_, Event, Event, _>::from(stream).for_async();
-        futures::stream::iter(0..n)
-            .map(|i| {
-                Ok(Event {
-                    id: Bytes::from(i.to_string()),
-                    data: Bytes::from_static(b"goodbye world"),
+        let (mut reader, writer) = client.for_async().split();
+        let sender = tokio::spawn(
+            futures::stream::iter(0..n)
+                .map(|i| {
+                    Ok(Event {
+                        id: Bytes::from(i.to_string()),
+                        data: Bytes::from_static(b"goodbye world"),
+                    })
                 })
-            })
-            .forward(&mut client)
-            .await
-            .unwrap();
-
-        let stream = client.get_mut();
-        stream.flush().await.unwrap();
+                .forward(writer),
+        );
 
         let mut at = 0usize;
-        while let Some(got) = client.next().await.transpose().unwrap() {
+        while let Some(got) = reader.next().await.transpose().unwrap() {
             assert_eq!(Bytes::from(at.to_string()), got.id);
             at += 1;
         }
+        assert_eq!(at, n);
+        sender.await.unwrap().unwrap();
     }
 }
@@ -0,0 +1,141 @@
+use std::{collections::BTreeMap, str::FromStr};
+
+use prost::Message;
+
+use crate::{Frame, ShallDecodeBody};
+
+/// key of the request id entry
+pub const REQUEST_ID: &str = "request-id";
+/// key of the content type entry
+pub const CONTENT_TYPE: &str = "content-type";
+/// key of the authorization entry
+pub const AUTHORIZATION: &str = "authorization";
+
+/// A ready-made header with string and binary key-value entries.
+///
+/// Keys are stored in a sorted map, so the same entries are always encoded the same way. Like any
+/// header, the encoded metadata must fit in `MAX_HEADER_LEN` bytes, unless frame flags are enabled
+/// (e.g. `for_async_framed_with_flags`), which allow up to `MAX_LONG_HEADER_LEN` bytes. Enable
+/// them for entries like authorization tokens, a JWT easily takes more than 255 bytes.
+#[derive(Clone, PartialEq, Message)]
+pub struct Metadata {
+    /// string entries
+    #[prost(btree_map = "string, string", tag = "1")]
+    pub entries: BTreeMap<String, String>,
+    /// binary entries
+    #[prost(btree_map = "string, bytes", tag = "2")]
+    pub binary_entries: BTreeMap<String, Vec<u8>>,
+    /// keep the body raw instead of decoding it
+    #[prost(bool, tag = "3")]
+    pub raw_body: bool,
+}
+
+impl ShallDecodeBody for Metadata {
+    fn shall_decode_body(&self) -> bool {
+        !self.raw_body
+    }
+}
+
+impl Metadata {
+    /// get a string entry
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries.get(key).map(|v| v.as_str())
+    }
+
+    /// get a string entry and parse it, e.g. `get_as::<u64>("retry")`
+    pub fn get_as<T>(&self, key: &str) -> Option<Result<T, T::Err>>
+    where
+        T: FromStr,
+    {
+        self.get(key).map(|v| v.parse())
+    }
+
+    /// get a binary entry
+    pub fn get_bin(&self, key: &str) -> Option<&[u8]> {
+        self.binary_entries.get(key).map(|v| v.as_slice())
+    }
+
+    /// set a string entry, return the previous value
+    pub fn insert(&mut self, key: impl Into<String>, value: impl ToString) -> Option<String> {
+        self.entries.insert(key.into(), value.to_string())
+    }
+
+    /// set a binary entry, return the previous value
+    pub fn insert_bin(
+        &mut self,
+        key: impl Into<String>,
+        value: impl Into<Vec<u8>>,
+    ) -> Option<Vec<u8>> {
+        self.binary_entries.insert(key.into(), value.into())
+    }
+
+    /// remove a string entry
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        self.entries.remove(key)
+    }
+
+    /// remove a binary entry
+    pub fn remove_bin(&mut self, key: &str) -> Option<Vec<u8>> {
+        self.binary_entries.remove(key)
+    }
+
+    /// get the request id
+    pub fn request_id(&self) -> Option<&str> {
+        self.get(REQUEST_ID)
+    }
+
+    /// set the request id
+    pub fn set_request_id(&mut self, id: impl ToString) {
+        self.insert(REQUEST_ID, id);
+    }
+
+    /// get the content type
+    pub fn content_type(&self) -> Option<&str> {
+        self.get(CONTENT_TYPE)
+    }
+
+    /// set the content type
+    pub fn set_content_type(&mut self, content_type: impl ToString) {
+        self.insert(CONTENT_TYPE, content_type);
+    }
+
+    /// get the authorization token
+    pub fn authorization(&self) -> Option<&str> {
+        self.get(AUTHORIZATION)
+    }
+
+    /// set the authorization token
+    pub fn set_authorization(&mut self, token: impl ToString) {
+        self.insert(AUTHORIZATION, token);
+    }
+}
+
+impl<T, TR> Frame<Metadata, T, TR> {
+    /// get a string metadata entry
+    pub fn metadata(&self, key: &str) -> Option<&str> {
+        self.header.as_ref().and_then(|h| h.get(key))
+    }
+
+    /// get a binary metadata entry
+    pub fn metadata_bin(&self, key: &str) -> Option<&[u8]> {
+        self.header.as_ref().and_then(|h| h.get_bin(key))
+    }
+
+    /// set a string metadata entry, creating the header if needed
+    pub fn set_metadata(&mut self, key: impl Into<String>, value: impl ToString) -> Option<String> {
+        self.header
+            .get_or_insert_with(Metadata::default)
+            .insert(key, value)
+    }
+
+    /// set a binary metadata entry, creating the header if needed
+    pub fn set_metadata_bin(
+        &mut self,
+        key: impl Into<String>,
+        value: impl Into<Vec<u8>>,
+    ) -> Option<Vec<u8>> {
+        self.header
+            .get_or_insert_with(Metadata::default)
+            .insert_bin(key, value)
+    }
+}
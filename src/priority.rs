@@ -0,0 +1,188 @@
+use std::{
+    collections::VecDeque,
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_core::ready;
+use futures_sink::Sink;
+use tokio::io::AsyncWrite;
+
+use crate::{AsyncProstWriter, ProstWriterFor, WriteBuf};
+
+/// frames are handed to the writer in batches of at most this many bytes (or a single frame), so
+/// a high priority frame only waits for the batch being written
+const MAX_BATCH: usize = 16 * 1024;
+
+/// Priority class of a message sent with `PriorityWriter`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum Priority {
+    /// control messages, e.g. heartbeats and cancellations
+    High,
+    /// regular messages
+    #[default]
+    Normal,
+    /// bulk data
+    Low,
+}
+
+impl Priority {
+    const ALL: [Priority; 3] = [Priority::High, Priority::Normal, Priority::Low];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// A wrapper around `AsyncProstWriter` that accepts `(Priority, T)` and sends frames of higher
+/// priority first.
+///
+/// Each message is encoded on its own and queued by priority class. Frames are interleaved at
+/// frame boundaries only, and never reordered within a class. The water marks of the writer
+/// apply to everything buffered, queued frames included.
+#[derive(Debug)]
+pub struct PriorityWriter<W, T, D> {
+    writer: AsyncProstWriter<W, T, D>,
+    queues: [VecDeque<WriteBuf>; 3],
+    queued: usize,
+    draining: bool,
+}
+
+impl<W, T, D> AsyncProstWriter<W, T, D> {
+    /// accept messages with a priority class, see `PriorityWriter`
+    pub fn with_priorities(self) -> PriorityWriter<W, T, D> {
+        PriorityWriter {
+            writer: self,
+            queues: Default::default(),
+            queued: 0,
+            draining: false,
+        }
+    }
+}
+
+impl<W, T, D> PriorityWriter<W, T, D> {
+    /// returns the number of bytes buffered or queued but not yet written
+    pub fn buffered(&self) -> usize {
+        self.writer.buffered() + self.queued
+    }
+
+    /// returns the number of bytes queued with the priority
+    pub fn queued(&self, priority: Priority) -> usize {
+        self.queues[priority.index()].iter().map(|f| f.len()).sum()
+    }
+
+    /// gets a reference to the underlying writer
+    pub fn get_ref(&self) -> &AsyncProstWriter<W, T, D> {
+        &self.writer
+    }
+
+    /// gets a mutable reference to the underlying writer
+    pub fn get_mut(&mut self) -> &mut AsyncProstWriter<W, T, D> {
+        &mut self.writer
+    }
+
+    /// Unwrap the `PriorityWriter`, returning the underlying writer.
+    ///
+    /// The queued frames are moved to the writer in priority order, nothing is lost.
+    pub fn into_inner(mut self) -> AsyncProstWriter<W, T, D> {
+        for queue in self.queues.iter_mut() {
+            for frame in queue.drain(..) {
+                self.writer.append_encoded(frame);
+            }
+        }
+        self.writer
+    }
+
+    /// hand the next batch of frames to the writer, highest priority first
+    fn promote(&mut self) -> bool {
+        let mut batch = 0;
+        for priority in Priority::ALL {
+            let queue = &mut self.queues[priority.index()];
+            while let Some(frame) = queue.front() {
+                if batch > 0 && batch + frame.len() > MAX_BATCH {
+                    return true;
+                }
+                let frame = queue.pop_front().unwrap();
+                batch += frame.len();
+                self.queued -= frame.len();
+                self.writer.append_encoded(frame);
+            }
+        }
+        batch > 0
+    }
+}
+
+impl<W, T, D> PriorityWriter<W, T, D>
+where
+    W: AsyncWrite + Unpin,
+{
+    /// write out batches until at most `target` bytes are left
+    fn poll_write_until(&mut self, cx: &mut Context<'_>, target: usize) -> Poll<io::Result<()>> {
+        while self.buffered() > target {
+            if self.writer.buffered() == 0 && !self.promote() {
+                break;
+            }
+            ready!(self.writer.poll_write_until(cx, 0))?;
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_flush_untimed(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.poll_write_until(cx, 0))?;
+        self.draining = false;
+        self.writer.poll_flush_untimed(cx)
+    }
+
+    /// write out everything queued and flush, with the write timeout of the writer
+    fn poll_flush_all(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let before = self.buffered();
+        let res = self.poll_flush_untimed(cx);
+        let progressed = self.buffered() != before;
+        self.writer.poll_timed(cx, res, progressed)
+    }
+}
+
+impl<W, T, D> Sink<(Priority, T)> for PriorityWriter<W, T, D>
+where
+    W: AsyncWrite + Unpin,
+    AsyncProstWriter<W, T, D>: ProstWriterFor<T>,
+{
+    type Error = io::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        let (high_water_mark, low_water_mark) = this.writer.water_marks();
+        if this.buffered() > high_water_mark {
+            this.draining = true;
+        }
+
+        if this.draining {
+            ready!(this.poll_write_until(cx, low_water_mark))?;
+            this.draining = false;
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(
+        self: Pin<&mut Self>,
+        (priority, item): (Priority, T),
+    ) -> Result<(), Self::Error> {
+        let this = self.get_mut();
+        let frame = this.writer.encode_item(item)?;
+        this.queued += frame.len();
+        this.queues[priority.index()].push_back(frame);
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().poll_flush_all(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        ready!(this.poll_flush_all(cx))?;
+        this.writer.poll_close_all(cx)
+    }
+}
@@ -11,7 +11,11 @@ use futures_core::{ready, Stream};
 use prost::Message;
 use tokio::io::{AsyncRead, ReadBuf};
 
-use crate::{AsyncDestination, AsyncFrameDestination, Framed};
+use crate::{
+    framing::Prefix, AsyncBatchDestination, AsyncDestination, AsyncFlaggedFrameDestination,
+    AsyncFrameDestination, ConfiguredDestination, ConfiguredFrameDestination, FrameFlags, Framed,
+    FramingConfig, MAX_LONG_HEADER_LEN,
+};
 
 const BUFFER_SIZE: usize = 8192;
 const LEN_SIZE: usize = 4;
@@ -25,7 +29,9 @@ enum FillResult {
 #[derive(Debug)]
 pub struct AsyncProstReader<R, T, D> {
     reader: R,
-    pub(crate) buffer: BytesMut,
+    buffer: BytesMut,
+    batch_left: usize,
+    framing: FramingConfig,
     into: PhantomData<T>,
     dest: PhantomData<D>,
 }
@@ -37,6 +43,8 @@ impl<R, T, D> AsyncProstReader<R, T, D> {
         Self {
             reader,
             buffer: BytesMut::with_capacity(BUFFER_SIZE),
+            batch_left: 0,
+            framing: FramingConfig::default(),
             into: PhantomData,
             dest: PhantomData,
         }
@@ -52,6 +60,11 @@ impl<R, T, D> AsyncProstReader<R, T, D> {
         &mut self.reader
     }
 
+    /// set the framing used by the configured destinations
+    pub(crate) fn set_framing(&mut self, framing: FramingConfig) {
+        self.framing = framing;
+    }
+
     /// returns a reference to the internally buffered data
     pub fn buffer(&self) -> &[u8] {
         &self.buffer[..]
@@ -61,6 +74,76 @@ impl<R, T, D> AsyncProstReader<R, T, D> {
     pub fn into_inner(self) -> R {
         self.reader
     }
+
+    /// Change the type of the values this reader produces.
+    ///
+    /// The bytes already read from the underlying reader but not decoded yet stay buffered and
+    /// are decoded as `T2`. The destination and the framing are kept, only the message type
+    /// changes.
+    pub fn retype<T2>(self) -> AsyncProstReader<R, T2, D> {
+        AsyncProstReader {
+            reader: self.reader,
+            buffer: self.buffer,
+            batch_left: self.batch_left,
+            framing: self.framing,
+            into: PhantomData,
+            dest: self.dest,
+        }
+    }
+
+    /// switch to another destination, whose framing starts from the default limits
+    pub(crate) fn make_for<D2>(self) -> AsyncProstReader<R, T, D2> {
+        AsyncProstReader {
+            reader: self.reader,
+            buffer: self.buffer,
+            batch_left: self.batch_left,
+            framing: FramingConfig::default(),
+            into: self.into,
+            dest: PhantomData,
+        }
+    }
+
+    /// steal the buffered state of this reader, leaving it empty
+    pub(crate) fn take_state(&mut self) -> AsyncProstReader<(), T, D> {
+        let mut state = AsyncProstReader::new(());
+        state.buffer = self.buffer.split();
+        state.batch_left = std::mem::take(&mut self.batch_left);
+        state.framing = self.framing;
+        state
+    }
+
+    /// replace the underlying reader, keeping the buffered state
+    pub(crate) fn with_reader<R2>(self, reader: R2) -> AsyncProstReader<R2, T, D> {
+        AsyncProstReader {
+            reader,
+            buffer: self.buffer,
+            batch_left: self.batch_left,
+            framing: self.framing,
+            into: self.into,
+            dest: self.dest,
+        }
+    }
+}
+
+impl<R, T> AsyncProstReader<R, T, ConfiguredDestination> {
+    /// Read values framed as set by `framing`, chosen at runtime.
+    ///
+    /// A `Prefix::None` or `Prefix::Frame` framing makes every read fail, use a reader for
+    /// `ConfiguredFrameDestination` to read frames.
+    pub fn with_framing(mut self, framing: FramingConfig) -> Self {
+        self.framing = framing;
+        self
+    }
+}
+
+impl<R, T> AsyncProstReader<R, T, ConfiguredFrameDestination> {
+    /// Read frames framed as set by `framing`, chosen at runtime.
+    ///
+    /// Any prefix but `Prefix::Frame` makes every read fail.
+    pub fn with_framing(mut self, framing: FramingConfig) -> Self {
+        self.framing = framing;
+        self
+    }
 }
 
 impl<R, T, D> Default for AsyncProstReader<R, T, D>
@@ -85,25 +168,98 @@ where
 {
     type Item = Result<T, io::Error>;
 
-    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        // FIXME: what 5 means here?
-        if let FillResult::Eof = ready!(self.as_mut().fill(cx, 5))? {
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.poll_next_with(cx, |buf| Ok(Message::decode(buf)?))
+    }
+}
+
+impl<R, T, D> AsyncProstReader<R, T, D>
+where
+    R: AsyncRead + Unpin,
+{
+    /// read the next length delimited message, and decode it with `decode`
+    pub(crate) fn poll_next_with<M, F>(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        decode: F,
+    ) -> Poll<Option<Result<M, io::Error>>>
+    where
+        F: FnOnce(&[u8]) -> Result<M, io::Error>,
+    {
+        if let FillResult::Eof = ready!(self.as_mut().fill(cx, LEN_SIZE))? {
             return Poll::Ready(None);
         }
 
         let message_size = NetworkEndian::read_u32(&self.buffer[..LEN_SIZE]) as usize;
+        self.framing.check_len(message_size)?;
 
         // since self.buffer.len() >= 4, we know that we can't get a clean EOF here
         ready!(self.as_mut().fill(cx, message_size + LEN_SIZE))?;
 
         self.buffer.advance(LEN_SIZE);
-        let message =
-            Message::decode(&self.buffer[..message_size]).map_err(prost::DecodeError::from)?;
+        let message = decode(&self.buffer[..message_size])?;
         self.buffer.advance(message_size);
         Poll::Ready(Some(Ok(message)))
     }
 }
 
+impl<R, T> Stream for AsyncProstReader<R, T, AsyncBatchDestination>
+where
+    T: Message + Default,
+    R: AsyncRead + Unpin,
+{
+    type Item = Result<T, io::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.poll_next_batched(cx)
+    }
+}
+
+impl<R, T, D> AsyncProstReader<R, T, D>
+where
+    T: Message + Default,
+    R: AsyncRead + Unpin,
+{
+    /// read the next value of a batch, reading the next batch if needed
+    fn poll_next_batched(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<T, io::Error>>> {
+        // read batches until a non-empty one
+        while self.batch_left == 0 {
+            if let FillResult::Eof = ready!(self.as_mut().fill(cx, LEN_SIZE))? {
+                return Poll::Ready(None);
+            }
+
+            let batch_size = NetworkEndian::read_u32(&self.buffer[..LEN_SIZE]) as usize;
+            self.framing.check_len(batch_size)?;
+
+            // since self.buffer.len() >= 4, we know that we can't get a clean EOF here
+            ready!(self.as_mut().fill(cx, batch_size + LEN_SIZE))?;
+
+            self.buffer.advance(LEN_SIZE);
+            self.batch_left = batch_size;
+        }
+
+        // the whole batch is buffered, unpack the values one by one
+        let mut batch = &self.buffer[..self.batch_left];
+        match T::decode_length_delimited(&mut batch) {
+            Ok(message) => {
+                let consumed = self.batch_left - batch.len();
+                self.buffer.advance(consumed);
+                self.batch_left -= consumed;
+                Poll::Ready(Some(Ok(message)))
+            }
+            Err(e) => {
+                // the rest of the batch can't be trusted
+                let batch_left = std::mem::take(&mut self.batch_left);
+                self.buffer.advance(batch_left);
+                Poll::Ready(Some(Err(e.into())))
+            }
+        }
+    }
+}
+
 impl<R, T> Stream for AsyncProstReader<R, T, AsyncFrameDestination>
 where
     R: AsyncRead + Unpin,
@@ -111,22 +267,107 @@ where
 {
     type Item = Result<T, io::Error>;
 
-    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        // FIXME: what 5 means here?
-        if let FillResult::Eof = ready!(self.as_mut().fill(cx, LEN_SIZE + 1))? {
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.poll_next_frame(cx, false)
+    }
+}
+
+impl<R, T> Stream for AsyncProstReader<R, T, AsyncFlaggedFrameDestination>
+where
+    R: AsyncRead + Unpin,
+    T: Framed + Default,
+{
+    type Item = Result<T, io::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.poll_next_frame(cx, true)
+    }
+}
+
+impl<R, T> Stream for AsyncProstReader<R, T, ConfiguredDestination>
+where
+    T: Message + Default,
+    R: AsyncRead + Unpin,
+{
+    type Item = Result<T, io::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match self.framing.prefix {
+            Prefix::Length => self.poll_next_with(cx, |buf| Ok(T::decode(buf)?)),
+            Prefix::Batch => self.poll_next_batched(cx),
+            Prefix::None | Prefix::Frame => Poll::Ready(Some(Err(self.framing.unsupported()))),
+        }
+    }
+}
+
+impl<R, T> Stream for AsyncProstReader<R, T, ConfiguredFrameDestination>
+where
+    R: AsyncRead + Unpin,
+    T: Framed + Default,
+{
+    type Item = Result<T, io::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match self.framing.prefix {
+            Prefix::Frame => {
+                let with_flags = self.framing.flags;
+                self.poll_next_frame(cx, with_flags)
+            }
+            _ => Poll::Ready(Some(Err(self.framing.unsupported()))),
+        }
+    }
+}
+
+impl<R, T, D> AsyncProstReader<R, T, D>
+where
+    R: AsyncRead + Unpin,
+    T: Framed + Default,
+{
+    fn poll_next_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        with_flags: bool,
+    ) -> Poll<Option<Result<T, io::Error>>> {
+        let prefix_size = if with_flags { LEN_SIZE + 1 } else { LEN_SIZE };
+        if let FillResult::Eof = ready!(self.as_mut().fill(cx, prefix_size))? {
             return Poll::Ready(None);
         }
 
         let size = NetworkEndian::read_u32(&self.buffer[..LEN_SIZE]) as usize;
-        let header_size = size >> 24;
+        let mut flags = with_flags.then(|| FrameFlags::from_bits(self.buffer[LEN_SIZE]));
         let body_size = 0x00ffffff & size;
+        let (header_size, prefix_size) = match flags.as_mut() {
+            Some(flags) if flags.contains(FrameFlags::LONG_HEADER) => {
+                // the header length follows the flags
+                flags.remove(FrameFlags::LONG_HEADER);
+                ready!(self.as_mut().fill(cx, prefix_size + LEN_SIZE))?;
+                let buf = &self.buffer[prefix_size..prefix_size + LEN_SIZE];
+                let header_size = NetworkEndian::read_u32(buf) as usize;
+                // checked before reserving room for it, as the length comes from the peer
+                if header_size > MAX_LONG_HEADER_LEN {
+                    return Poll::Ready(Some(Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "header of {} bytes exceeds the limit of {}",
+                            header_size, MAX_LONG_HEADER_LEN
+                        ),
+                    ))));
+                }
+                (header_size, prefix_size + LEN_SIZE)
+            }
+            _ => (size >> 24, prefix_size),
+        };
         let message_size = header_size + body_size;
+        self.framing.check_len(message_size)?;
 
         // since self.buffer.len() >= 4, we know that we can't get a clean EOF here
-        ready!(self.as_mut().fill(cx, message_size + LEN_SIZE))?;
+        ready!(self.as_mut().fill(cx, message_size + prefix_size))?;
 
-        self.buffer.advance(LEN_SIZE);
-        let message = T::decode(&self.buffer[..message_size], header_size)?;
+        self.buffer.advance(prefix_size);
+        let mut message = T::decode(&self.buffer[..message_size], header_size)?;
+        if let Some(flags) = flags {
+            message.set_flags(flags);
+        }
 
         self.buffer.advance(message_size);
         Poll::Ready(Some(Ok(message)))
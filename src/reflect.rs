@@ -0,0 +1,205 @@
+use std::{
+    io,
+    ops::{Deref, DerefMut},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use bytes::BufMut;
+use either::Either;
+use futures_core::{ready, Stream};
+use prost::Message;
+use prost_reflect::{DynamicMessage, MessageDescriptor};
+use tokio::io::AsyncRead;
+
+use crate::{
+    frame::{encode_parts, encoded_body_len, encoded_header_len},
+    AsyncDestination, AsyncProstReader, Frame, FrameFlags, Framed, ShallDecodeBody, Trailer,
+    WriteBuf,
+};
+
+/// A frame body of a message type only known at runtime.
+///
+/// Without a `MessageDescriptor`, `Framed::decode` keeps the body raw. Use `DynamicReader` or
+/// `Frame::decode_dynamic_body` to decode it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DynamicBody(pub DynamicMessage);
+
+impl Deref for DynamicBody {
+    type Target = DynamicMessage;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for DynamicBody {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl From<DynamicMessage> for DynamicBody {
+    fn from(msg: DynamicMessage) -> Self {
+        Self(msg)
+    }
+}
+
+impl<H, TR> Frame<H, DynamicBody, TR>
+where
+    H: ShallDecodeBody,
+{
+    /// decode the raw body with the descriptor, if the header asks for it
+    pub fn decode_dynamic_body(&mut self, desc: &MessageDescriptor) -> Result<(), io::Error> {
+        let decode_body = self
+            .header
+            .as_ref()
+            .map(|h| h.shall_decode_body())
+            .unwrap_or(true);
+        if let (true, Some(Either::Left(raw))) = (decode_body, self.body.as_ref()) {
+            let msg = DynamicMessage::decode(desc.clone(), raw.as_slice())?;
+            self.body = Some(Either::Right(DynamicBody(msg)));
+        }
+        Ok(())
+    }
+}
+
+impl<H, TR> Framed for Frame<H, DynamicBody, TR>
+where
+    H: Message + ShallDecodeBody + Default,
+    TR: Trailer,
+{
+    fn decode(buf: &[u8], header_len: usize) -> Result<Self, io::Error>
+    where
+        Self: Default,
+    {
+        let header = if header_len > 0 {
+            H::decode(&buf[0..header_len])?
+        } else {
+            H::default()
+        };
+
+        // body is kept raw, as the descriptor is unknown here
+        let (body_buf, trailer) = Self::split_trailer(&buf[header_len..])?;
+        Ok(Self {
+            header: Some(header),
+            body: Some(Either::Left(body_buf.to_vec())),
+            trailer,
+            flags: FrameFlags::default(),
+        })
+    }
+
+    fn flags(&self) -> FrameFlags {
+        self.flags.with_header_only(self.body.is_none())
+    }
+
+    fn set_flags(&mut self, flags: FrameFlags) {
+        if flags.contains(FrameFlags::HEADER_ONLY) {
+            self.body = None;
+        }
+        self.flags = flags;
+    }
+
+    fn header_len(&self) -> Result<usize, io::Error> {
+        Ok(encoded_header_len(self.header.as_ref()))
+    }
+
+    fn body_len(&self) -> Result<usize, io::Error> {
+        let body_len = encoded_body_len(self.body.as_ref(), |v| v.0.encoded_len());
+        Ok(body_len + self.trailer_section_len())
+    }
+
+    fn encode<B>(&self, buf: &mut B) -> Result<(), io::Error>
+    where
+        B: BufMut,
+        Self: Sized,
+    {
+        encode_parts(self.header.as_ref(), self.body.as_ref(), buf, |v, buf| {
+            Ok(v.0.encode(buf)?)
+        })?;
+        self.write_trailer(buf)
+    }
+
+    fn encode_into(self, buf: &mut WriteBuf) -> Result<(), io::Error> {
+        self.encode_frame_into(buf, |v, buf| Ok(v.0.encode(buf)?))
+    }
+}
+
+/// A wrapper around `AsyncProstReader` that decodes messages of a runtime known type with the
+/// given `MessageDescriptor`.
+///
+/// Use `DynamicMessage` as the item type for prost-encoded values, or `Frame<H, DynamicBody>`
+/// for frames.
+#[derive(Debug)]
+pub struct DynamicReader<R, T, D> {
+    reader: AsyncProstReader<R, T, D>,
+    descriptor: MessageDescriptor,
+}
+
+impl<R, T, D> AsyncProstReader<R, T, D> {
+    /// decode the messages with the descriptor
+    pub fn with_descriptor(self, descriptor: MessageDescriptor) -> DynamicReader<R, T, D> {
+        DynamicReader::new(self, descriptor)
+    }
+}
+
+impl<R, T, D> DynamicReader<R, T, D> {
+    /// create a new dynamic reader
+    pub fn new(reader: AsyncProstReader<R, T, D>, descriptor: MessageDescriptor) -> Self {
+        Self { reader, descriptor }
+    }
+
+    /// gets a reference to the descriptor of the messages
+    pub fn descriptor(&self) -> &MessageDescriptor {
+        &self.descriptor
+    }
+
+    /// gets a reference to the underlying reader
+    pub fn get_ref(&self) -> &AsyncProstReader<R, T, D> {
+        &self.reader
+    }
+
+    /// gets a mutable reference to the underlying reader
+    pub fn get_mut(&mut self) -> &mut AsyncProstReader<R, T, D> {
+        &mut self.reader
+    }
+
+    /// unwrap the `DynamicReader`, returning the underlying reader
+    pub fn into_inner(self) -> AsyncProstReader<R, T, D> {
+        self.reader
+    }
+}
+
+impl<R> Stream for DynamicReader<R, DynamicMessage, AsyncDestination>
+where
+    R: AsyncRead + Unpin,
+{
+    type Item = Result<DynamicMessage, io::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let desc = &this.descriptor;
+        Pin::new(&mut this.reader)
+            .poll_next_with(cx, |buf| Ok(DynamicMessage::decode(desc.clone(), buf)?))
+    }
+}
+
+impl<R, H, TR, D> Stream for DynamicReader<R, Frame<H, DynamicBody, TR>, D>
+where
+    R: Unpin,
+    H: ShallDecodeBody,
+    AsyncProstReader<R, Frame<H, DynamicBody, TR>, D>:
+        Stream<Item = Result<Frame<H, DynamicBody, TR>, io::Error>>,
+{
+    type Item = Result<Frame<H, DynamicBody, TR>, io::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let mut frame = match ready!(Pin::new(&mut this.reader).poll_next(cx)) {
+            Some(Ok(frame)) => frame,
+            other => return Poll::Ready(other),
+        };
+        frame.decode_dynamic_body(&this.descriptor)?;
+        Poll::Ready(Some(Ok(frame)))
+    }
+}
@@ -1,28 +1,34 @@
 use std::{
-    fmt, io,
+    error::Error,
+    fmt,
     ops::{Deref, DerefMut},
     pin::Pin,
     task::{Context, Poll},
+    time::Duration,
 };
 
 use futures_core::Stream;
 use futures_sink::Sink;
+#[cfg(unix)]
+use tokio::net::{unix, UnixStream};
 use tokio::{
-    io::{AsyncRead, ReadBuf},
-    net::{
-        tcp::{ReadHalf, WriteHalf},
-        TcpStream,
-    },
+    io::{self, AsyncRead, AsyncWrite, DuplexStream, ReadBuf},
+    net::{tcp, TcpStream},
 };
 
 use crate::{
-    AsyncDestination, AsyncFrameDestination, AsyncProstReader, AsyncProstWriter, SyncDestination,
+    AsyncBatchDestination, AsyncDestination, AsyncFlaggedFrameDestination, AsyncFrameDestination,
+    AsyncProstReader, AsyncProstWriter, ConfiguredDestination, ConfiguredFrameDestination,
+    FlushPolicy, FramingConfig, ItemSink, Joined, ProstWriterFor, SyncDestination,
 };
 
 /// A wrapper around an async stream that receives and sends prost-encoded values
+///
+/// Values are read as set by `D` and written as set by `WD`, which is the same unless changed by
+/// `with_write_framing`.
 #[derive(Debug)]
-pub struct AsyncProstStream<S, R, W, D> {
-    stream: AsyncProstReader<InternalAsyncWriter<S, W, D>, R, D>,
+pub struct AsyncProstStream<S, R, W, D, WD = D> {
+    stream: AsyncProstReader<InternalAsyncWriter<S, W, WD>, R, D>,
 }
 
 #[doc(hidden)]
@@ -64,7 +70,7 @@ impl<S, R, W> From<S> for AsyncProstStream<S, R, W, SyncDestination> {
     }
 }
 
-impl<S, R, W, D> AsyncProstStream<S, R, W, D> {
+impl<S, R, W, D, WD> AsyncProstStream<S, R, W, D, WD> {
     /// Gets a reference to the underlying stream.
     ///
     /// It is inadvisable to directly read from or write to the underlying stream.
@@ -88,62 +94,368 @@ impl<S, R, W, D> AsyncProstStream<S, R, W, D> {
     }
 }
 
-impl<S, R, W, D> AsyncProstStream<S, R, W, D> {
-    /// make this stream include the serialized data's size before each serialized value
+impl<S, R, W, D, WD> AsyncProstStream<S, R, W, D, WD> {
+    /// Make `poll_ready` apply backpressure, see `AsyncProstWriter::with_backpressure`.
+    pub fn with_backpressure(mut self, high_water_mark: usize, low_water_mark: usize) -> Self {
+        self.stream
+            .get_mut()
+            .set_backpressure(high_water_mark, low_water_mark);
+        self
+    }
+
+    /// Make the stream flush by itself according to the policy, see `FlushPolicy`.
+    pub fn with_flush_policy(mut self, policy: FlushPolicy) -> Self {
+        self.stream.get_mut().set_flush_policy(policy);
+        self
+    }
+
+    /// Make `flush` and `close` fail once nothing could be written for the duration, see
+    /// `AsyncProstWriter::with_write_timeout`.
+    pub fn with_write_timeout(mut self, timeout: Duration) -> Self {
+        self.stream.get_mut().set_write_timeout(Some(timeout));
+        self
+    }
+
+    /// Make this stream include the serialized data's size before each serialized value.
+    ///
+    /// Like the other mode switches, it keeps the buffered data and the settings, so it can be
+    /// used mid-connection.
     pub fn for_async(self) -> AsyncProstStream<S, R, W, AsyncDestination> {
-        let stream = self.into_inner();
-        AsyncProstStream {
-            stream: AsyncProstReader::from(InternalAsyncWriter(
-                AsyncProstWriter::from(stream).for_async(),
-            )),
-        }
+        self.convert()
     }
 
     /// make this stream include the serialized data's size before each serialized value
     pub fn for_async_framed(self) -> AsyncProstStream<S, R, W, AsyncFrameDestination> {
-        let stream = self.into_inner();
-        AsyncProstStream {
-            stream: AsyncProstReader::from(InternalAsyncWriter(
-                AsyncProstWriter::from(stream).for_async_framed(),
-            )),
-        }
+        self.convert()
+    }
+
+    /// make this stream include the serialized data's size and the frame flags before each
+    /// serialized value
+    pub fn for_async_framed_with_flags(
+        self,
+    ) -> AsyncProstStream<S, R, W, AsyncFlaggedFrameDestination> {
+        self.convert()
+    }
+
+    /// make this stream pack the serialized values into batch frames, see
+    /// `AsyncProstWriter::for_async_batched`
+    pub fn for_async_batched(
+        self,
+        max_bytes: usize,
+        max_count: usize,
+    ) -> Result<AsyncProstStream<S, R, W, AsyncBatchDestination>, io::Error> {
+        let mut stream = self.convert();
+        stream
+            .stream
+            .get_mut()
+            .set_batch_limits(max_bytes, max_count)?;
+        Ok(stream)
     }
 
     /// Make this stream only send prost-encoded values
     pub fn for_sync(self) -> AsyncProstStream<S, R, W, SyncDestination> {
-        AsyncProstStream::from(self.into_inner())
+        self.convert()
+    }
+
+    /// make this stream frame prost values as set by `framing`, chosen at runtime, see
+    /// `AsyncProstWriter::for_configured`
+    pub fn for_configured(
+        self,
+        framing: FramingConfig,
+    ) -> Result<AsyncProstStream<S, R, W, ConfiguredDestination>, io::Error> {
+        self.convert().with_framing(framing)
+    }
+
+    /// make this stream frame `Framed` values as set by `framing`, chosen at runtime, see
+    /// `AsyncProstWriter::for_configured_framed`
+    pub fn for_configured_framed(
+        self,
+        framing: FramingConfig,
+    ) -> Result<AsyncProstStream<S, R, W, ConfiguredFrameDestination>, io::Error> {
+        self.convert().with_framing(framing)
+    }
+
+    /// set the framing of both directions, if its limits are valid
+    fn with_framing(mut self, framing: FramingConfig) -> Result<Self, io::Error> {
+        self.stream.get_mut().set_framing(framing)?;
+        self.stream.set_framing(framing);
+        Ok(self)
+    }
+
+    /// Change the types of the values this stream receives and sends.
+    ///
+    /// Both the buffered read data and the buffered write data are kept, see
+    /// `AsyncProstReader::retype` and `AsyncProstWriter::retype`. Unlike the `for_*` methods, the
+    /// framing stays the same, only the message types change.
+    pub fn retype<R2, W2>(self) -> AsyncProstStream<S, R2, W2, D, WD> {
+        let mut reader = self.stream;
+        let rstate = reader.take_state().retype();
+        let writer = reader.into_inner().0.retype();
+
+        AsyncProstStream {
+            stream: rstate.with_reader(InternalAsyncWriter(writer)),
+        }
+    }
+
+    /// Frame the values written differently from the values read, keeping the buffered data and
+    /// the settings.
+    ///
+    /// `framing` is given the writer, for it to choose the framing with `for_async` and friends,
+    /// e.g. `stream.for_async().with_write_framing(|w| Ok(w.for_async_framed()))`, or
+    /// `|w| w.for_configured(framing)` for a framing chosen at runtime. Fails with the error of
+    /// `framing`.
+    pub fn with_write_framing<WD2, F>(
+        self,
+        framing: F,
+    ) -> Result<AsyncProstStream<S, R, W, D, WD2>, io::Error>
+    where
+        F: FnOnce(
+            AsyncProstWriter<S, W, SyncDestination>,
+        ) -> Result<AsyncProstWriter<S, W, WD2>, io::Error>,
+    {
+        let mut reader = self.stream;
+        let rstate = reader.take_state();
+        let writer = framing(reader.into_inner().0.make_for())?;
+
+        Ok(AsyncProstStream {
+            stream: rstate.with_reader(InternalAsyncWriter(writer)),
+        })
+    }
+
+    /// change the destinations, keeping the buffered state and the settings but the framing
+    fn convert<D2, WD2>(self) -> AsyncProstStream<S, R, W, D2, WD2> {
+        let mut reader = self.stream;
+        let rstate = reader.take_state().make_for();
+        let writer = reader.into_inner().0.make_for();
+
+        AsyncProstStream {
+            stream: rstate.with_reader(InternalAsyncWriter(writer)),
+        }
     }
 }
 
-impl<R, W, D> AsyncProstStream<TcpStream, R, W, D> {
+impl<S, R, W, D, WD> AsyncProstStream<S, R, W, D, WD>
+where
+    S: AsyncWrite + Unpin,
+{
+    /// Buffer a value the writer accepts besides `W`, e.g. `&W` or a pre-encoded value, see
+    /// `AsyncProstWriter::feed_item`.
+    pub async fn feed_item<I>(&mut self, item: I) -> io::Result<()>
+    where
+        AsyncProstWriter<S, W, WD>: ProstWriterFor<I>,
+    {
+        self.stream.get_mut().feed_item(item).await
+    }
+
+    /// Buffer a value the writer accepts besides `W` and flush, see
+    /// `AsyncProstWriter::send_item`.
+    pub async fn send_item<I>(&mut self, item: I) -> io::Result<()>
+    where
+        AsyncProstWriter<S, W, WD>: ProstWriterFor<I>,
+    {
+        self.stream.get_mut().send_item(item).await
+    }
+
+    /// Borrow the writing side as a `Sink` of a value it accepts besides `W`, see
+    /// `AsyncProstWriter::item_sink`.
+    pub fn item_sink<I>(&mut self) -> ItemSink<'_, S, W, WD, I>
+    where
+        AsyncProstWriter<S, W, WD>: ProstWriterFor<I>,
+    {
+        self.stream.get_mut().item_sink()
+    }
+
+    /// Wait until the flush policy asks for a flush, then write out everything buffered, see
+    /// `AsyncProstWriter::flush_when_due`.
+    pub async fn flush_when_due(&mut self) -> io::Result<()> {
+        self.stream.get_mut().flush_when_due().await
+    }
+}
+
+impl<S, R, W, D, WD> AsyncProstStream<S, R, W, D, WD> {
+    /// split the underlying stream with `split`, keeping the buffered state in the halves
+    fn split_with<RH, WH, F>(
+        self,
+        split: F,
+    ) -> (AsyncProstReader<RH, R, D>, AsyncProstWriter<WH, W, WD>)
+    where
+        F: FnOnce(S) -> (RH, WH),
+    {
+        let mut reader = self.stream;
+        let rstate = reader.take_state();
+        let mut writer = reader.into_inner().0;
+        let wstate = writer.take_state();
+        let (r, w) = split(writer.into_inner());
+
+        (rstate.with_reader(r), wstate.with_writer(w))
+    }
+}
+
+impl<S, R, W, D, WD> AsyncProstStream<S, R, W, D, WD>
+where
+    S: AsyncRead + AsyncWrite,
+{
+    /// Split the stream into a read half and a write half, which can be used from different tasks.
+    ///
+    /// Unlike `tcp_split`, it works with any stream, at the cost of a lock around it. The buffered
+    /// data isn't lost, use `reunite` to put the halves back together.
+    pub fn split(self) -> (SplitReader<S, R, D>, SplitWriter<S, W, WD>) {
+        self.split_with(io::split)
+    }
+
+    /// Put the halves from `split` back together, keeping the buffered data.
+    ///
+    /// Fails if the halves don't come from the same stream, giving them back.
+    // the halves are given back on error, like tokio does
+    #[allow(clippy::result_large_err, clippy::type_complexity)]
+    pub fn reunite(
+        reader: SplitReader<S, R, D>,
+        writer: SplitWriter<S, W, WD>,
+    ) -> Result<Self, ReuniteError<SplitReader<S, R, D>, SplitWriter<S, W, WD>>>
+    where
+        S: Unpin,
+    {
+        if !reader.get_ref().is_pair_of(writer.get_ref()) {
+            return Err(ReuniteError(reader, writer));
+        }
+
+        let (mut reader, mut writer) = (reader, writer);
+        let rstate = reader.take_state();
+        let wstate = writer.take_state();
+        let stream = reader.into_inner().unsplit(writer.into_inner());
+
+        Ok(Self {
+            stream: rstate.with_reader(InternalAsyncWriter(wstate.with_writer(stream))),
+        })
+    }
+}
+
+/// read half of a stream split by `AsyncProstStream::split`
+pub type SplitReader<S, R, D> = AsyncProstReader<io::ReadHalf<S>, R, D>;
+
+/// write half of a stream split by `AsyncProstStream::split`
+pub type SplitWriter<S, W, D> = AsyncProstWriter<io::WriteHalf<S>, W, D>;
+
+/// Error of `AsyncProstStream::reunite` when the halves don't come from the same stream.
+#[derive(Debug)]
+pub struct ReuniteError<R, W>(pub R, pub W);
+
+impl<R, W> fmt::Display for ReuniteError<R, W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "tried to reunite halves that are not from the same stream"
+        )
+    }
+}
+
+impl<R: fmt::Debug, W: fmt::Debug> Error for ReuniteError<R, W> {}
+
+impl<R, W, D, WD> AsyncProstStream<TcpStream, R, W, D, WD> {
     /// split a TCP-based stream into a read half and a write half
     pub fn tcp_split(
         &mut self,
     ) -> (
-        AsyncProstReader<ReadHalf, R, D>,
-        AsyncProstWriter<WriteHalf, W, D>,
+        AsyncProstReader<tcp::ReadHalf<'_>, R, D>,
+        AsyncProstWriter<tcp::WriteHalf<'_>, W, WD>,
     ) {
         // first, steal the reader state so it isn't lost
-        let rbuff = self.stream.buffer.split();
+        let rstate = self.stream.take_state();
         // then fish out the writer
         let writer = &mut self.stream.get_mut().0;
         // and steal the writer state so it isn't lost
-        let wbuff = writer.buffer.split_off(0);
-        let wsize = writer.written;
+        let wstate = writer.take_state();
         // now split the stream
         let (r, w) = writer.get_mut().split();
         // then put the reader back together
-        let mut reader = AsyncProstReader::from(r);
-        reader.buffer = rbuff;
+        let reader = rstate.with_reader(r);
         // and then writer
-        let mut writer = AsyncProstWriter::from(w).make_for();
-        writer.buffer = wbuff;
-        writer.written = wsize;
+        let writer = wstate.with_writer(w);
 
         (reader, writer)
     }
 }
 
+impl<R, W> AsyncProstStream<DuplexStream, R, W, SyncDestination> {
+    /// Create two streams connected in memory, what one sends the other receives, e.g. for tests.
+    ///
+    /// They're backed by `tokio::io::duplex`, buffering up to `max_buf_size` bytes in each
+    /// direction.
+    ///
+    /// Like a stream built with `from`, both start as `SyncDestination`, which can't be read from.
+    /// Give each the same framing with `for_async`, `for_async_batched`, `for_async_framed` or
+    /// `for_configured`, as the other end of a socket would, or use `pair_for`.
+    pub fn pair(
+        max_buf_size: usize,
+    ) -> (Self, AsyncProstStream<DuplexStream, W, R, SyncDestination>) {
+        let (a, b) = io::duplex(max_buf_size);
+        (Self::from(a), AsyncProstStream::from(b))
+    }
+
+    /// Create two streams connected in memory, both framed as set by the destination `D2`, see
+    /// `pair`.
+    ///
+    /// E.g. `AsyncProstStream::<_, Response, Request, _>::pair_for::<AsyncDestination>(4096)`.
+    /// Batches and configured framings get the default limits of `FramingConfig`.
+    #[allow(clippy::type_complexity)]
+    pub fn pair_for<D2>(
+        max_buf_size: usize,
+    ) -> (
+        AsyncProstStream<DuplexStream, R, W, D2>,
+        AsyncProstStream<DuplexStream, W, R, D2>,
+    ) {
+        let (a, b) = Self::pair(max_buf_size);
+        (a.convert(), b.convert())
+    }
+}
+
+impl<RD, WR, R, W> AsyncProstStream<Joined<RD, WR>, R, W, SyncDestination> {
+    /// Build a stream from a separate reader and writer, e.g. stdin and stdout, or the pipes of a
+    /// child process.
+    ///
+    /// Choose the framing with `for_async` and friends, as for any other stream.
+    pub fn from_parts(reader: RD, writer: WR) -> Self {
+        Self::from(Joined::new(reader, writer))
+    }
+}
+
+impl<RD, WR, R, W, D, WD> AsyncProstStream<Joined<RD, WR>, R, W, D, WD> {
+    /// Take apart a stream built by `from_parts`.
+    ///
+    /// The reader and writer are returned wrapped, so that the buffered data isn't lost, use
+    /// `into_inner` on them to get the bare reader and writer.
+    pub fn into_parts(self) -> (AsyncProstReader<RD, R, D>, AsyncProstWriter<WR, W, WD>) {
+        self.split_with(Joined::into_inner)
+    }
+}
+
+impl<R, W, D, WD> AsyncProstStream<TcpStream, R, W, D, WD> {
+    /// Split a TCP-based stream into owned read and write halves, which can be moved to different
+    /// tasks. The buffered data isn't lost.
+    pub fn into_split(
+        self,
+    ) -> (
+        AsyncProstReader<tcp::OwnedReadHalf, R, D>,
+        AsyncProstWriter<tcp::OwnedWriteHalf, W, WD>,
+    ) {
+        self.split_with(TcpStream::into_split)
+    }
+}
+
+#[cfg(unix)]
+impl<R, W, D, WD> AsyncProstStream<UnixStream, R, W, D, WD> {
+    /// Split a Unix socket based stream into owned read and write halves, which can be moved to
+    /// different tasks. The buffered data isn't lost.
+    pub fn into_split(
+        self,
+    ) -> (
+        AsyncProstReader<unix::OwnedReadHalf, R, D>,
+        AsyncProstWriter<unix::OwnedWriteHalf, W, WD>,
+    ) {
+        self.split_with(UnixStream::into_split)
+    }
+}
+
 impl<S, T, D> AsyncRead for InternalAsyncWriter<S, T, D>
 where
     S: AsyncRead + Unpin,
@@ -157,10 +469,10 @@ where
     }
 }
 
-impl<S, R, W, D> Stream for AsyncProstStream<S, R, W, D>
+impl<S, R, W, D, WD> Stream for AsyncProstStream<S, R, W, D, WD>
 where
     S: Unpin,
-    AsyncProstReader<InternalAsyncWriter<S, W, D>, R, D>: Stream<Item = Result<R, io::Error>>,
+    AsyncProstReader<InternalAsyncWriter<S, W, WD>, R, D>: Stream<Item = Result<R, io::Error>>,
 {
     type Item = Result<R, io::Error>;
 
@@ -169,10 +481,10 @@ where
     }
 }
 
-impl<S, R, W, D> Sink<W> for AsyncProstStream<S, R, W, D>
+impl<S, R, W, D, WD> Sink<W> for AsyncProstStream<S, R, W, D, WD>
 where
     S: Unpin,
-    AsyncProstWriter<S, W, D>: Sink<W, Error = io::Error>,
+    AsyncProstWriter<S, W, WD>: Sink<W, Error = io::Error>,
 {
     type Error = io::Error;
 
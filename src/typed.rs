@@ -0,0 +1,181 @@
+use bytes::BufMut;
+use core::fmt::Debug;
+use either::Either;
+use prost::{DecodeError, EncodeError, Message};
+use std::{collections::HashMap, hash::Hash, io};
+
+use crate::{
+    frame::{encode_parts, encoded_body_len, encoded_header_len},
+    FrameFlags, Framed,
+};
+
+type Decoder<T> = Box<dyn Fn(&[u8]) -> Result<T, DecodeError> + Send + Sync>;
+
+/// header that carries the type of the message in the frame body
+pub trait MessageType {
+    /// type of the message id, e.g. `u32` or a type url `String`
+    type Id: Eq + Hash;
+
+    /// return the message id of the body
+    fn message_type(&self) -> Self::Id;
+}
+
+/// map message ids to the decoders of the body types
+pub struct BodyRegistry<K, T> {
+    decoders: HashMap<K, Decoder<T>>,
+}
+
+impl<K, T> Debug for BodyRegistry<K, T>
+where
+    K: Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_set().entries(self.decoders.keys()).finish()
+    }
+}
+
+impl<K, T> Default for BodyRegistry<K, T> {
+    fn default() -> Self {
+        Self {
+            decoders: HashMap::new(),
+        }
+    }
+}
+
+impl<K, T> BodyRegistry<K, T>
+where
+    K: Eq + Hash,
+{
+    /// create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// register message type `M` for the id, `f` wraps the decoded message into the body
+    pub fn register<M>(mut self, id: K, f: fn(M) -> T) -> Self
+    where
+        M: Message + Default + 'static,
+        T: 'static,
+    {
+        self.decoders
+            .insert(id, Box::new(move |buf| M::decode(buf).map(f)));
+        self
+    }
+
+    /// returns true if the id is registered
+    pub fn contains(&self, id: &K) -> bool {
+        self.decoders.contains_key(id)
+    }
+
+    /// decode the body with the decoder registered for the id, `None` if the id is unknown
+    pub fn decode(&self, id: &K, buf: &[u8]) -> Option<Result<T, DecodeError>> {
+        self.decoders.get(id).map(|decoder| decoder(buf))
+    }
+}
+
+/// a body which could be one of many message types, dispatched by its header
+pub trait RegisteredBody: Debug + Send + Sync + Sized + 'static {
+    /// type of the message id
+    type Id: Eq + Hash;
+
+    /// the registry used to decode the body
+    fn registry() -> &'static BodyRegistry<Self::Id, Self>;
+
+    /// encoded length of the body
+    fn encoded_len(&self) -> usize;
+
+    /// encode the body
+    fn encode(&self, buf: &mut dyn BufMut) -> Result<(), EncodeError>;
+}
+
+#[derive(Debug)]
+/// Decoded frame whose body type is chosen by the message type in its header.
+///
+/// Bodies with unknown message ids are kept as raw bytes.
+pub struct TypedFrame<H, T> {
+    /// header of the frame
+    pub header: Option<H>,
+    /// body of the frame
+    pub body: Option<Either<Vec<u8>, T>>,
+    /// flags of the frame, only sent for streams with frame flags enabled
+    pub flags: FrameFlags,
+}
+
+impl<H, T> TypedFrame<H, T> {
+    /// create a frame with the header and body, without flags
+    pub fn new(header: Option<H>, body: Option<Either<Vec<u8>, T>>) -> Self {
+        Self {
+            header,
+            body,
+            ..Default::default()
+        }
+    }
+}
+
+impl<H, T> Default for TypedFrame<H, T> {
+    fn default() -> Self {
+        Self {
+            header: None,
+            body: None,
+            flags: FrameFlags::default(),
+        }
+    }
+}
+
+impl<H, T> Framed for TypedFrame<H, T>
+where
+    H: Message + MessageType<Id = T::Id> + Default,
+    T: RegisteredBody,
+{
+    fn decode(buf: &[u8], header_len: usize) -> Result<Self, io::Error>
+    where
+        Self: Default,
+    {
+        let header = if header_len > 0 {
+            H::decode(&buf[0..header_len])?
+        } else {
+            H::default()
+        };
+
+        let body_buf = &buf[header_len..];
+        let body = match T::registry().decode(&header.message_type(), body_buf) {
+            Some(body) => Either::Right(body?),
+            None => Either::Left(body_buf.to_vec()),
+        };
+
+        Ok(Self {
+            header: Some(header),
+            body: Some(body),
+            flags: FrameFlags::default(),
+        })
+    }
+
+    fn flags(&self) -> FrameFlags {
+        self.flags.with_header_only(self.body.is_none())
+    }
+
+    fn set_flags(&mut self, flags: FrameFlags) {
+        if flags.contains(FrameFlags::HEADER_ONLY) {
+            self.body = None;
+        }
+        self.flags = flags;
+    }
+
+    fn header_len(&self) -> Result<usize, io::Error> {
+        Ok(encoded_header_len(self.header.as_ref()))
+    }
+
+    fn body_len(&self) -> Result<usize, io::Error> {
+        Ok(encoded_body_len(self.body.as_ref(), |v| v.encoded_len()))
+    }
+
+    fn encode<B>(&self, buf: &mut B) -> Result<(), io::Error>
+    where
+        B: BufMut,
+        Self: Sized,
+    {
+        encode_parts(self.header.as_ref(), self.body.as_ref(), buf, |v, buf| {
+            Ok(v.encode(buf)?)
+        })
+    }
+}
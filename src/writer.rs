@@ -1,24 +1,113 @@
 use std::{
-    io,
+    error::Error,
+    fmt,
+    future::{poll_fn, Future},
+    io::{self, IoSlice},
     marker::PhantomData,
     pin::Pin,
-    task::{Context, Poll},
+    sync::{Arc, OnceLock},
+    task::{Context, Poll, Wake, Waker},
+    time::Duration,
 };
 
-use byteorder::{NetworkEndian, WriteBytesExt};
+use bytes::BufMut;
 use futures_core::ready;
 use futures_sink::Sink;
-use prost::Message;
-use tokio::io::AsyncWrite;
+use prost::{
+    encoding::{encode_varint, encoded_len_varint},
+    Message,
+};
+use tokio::{
+    io::AsyncWrite,
+    time::{sleep_until, Instant, Sleep},
+};
+
+use crate::{
+    buf::MAX_IO_SLICES, frame::FrameLen, framing::Prefix, AsyncBatchDestination, AsyncDestination,
+    AsyncFlaggedFrameDestination, AsyncFrameDestination, ConfiguredDestination,
+    ConfiguredFrameDestination, EncodedFrame, EncodedMessage, Framed, FramingConfig,
+    SyncDestination, WriteBuf,
+};
+
+/// When `AsyncProstWriter` flushes the buffered data by itself.
+///
+/// `EveryMessage` and `Bytes` are applied as soon as a value is buffered, as far as the
+/// underlying writer accepts data without waiting; the rest goes out the next time the writer is
+/// polled. `Linger` starts a timer with the first buffered byte, which is checked whenever the
+/// writer is polled. To have lingering data go out while nothing else is sent, await
+/// `flush_when_due` next to the source of the values. Explicit flushes (`flush`, `send`, `close`)
+/// always write out everything. The data written to the wire is the same whatever the policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FlushPolicy {
+    /// only flush when asked to
+    #[default]
+    Manual,
+    /// flush every message before accepting the next one
+    EveryMessage,
+    /// flush once at least this many bytes are buffered
+    Bytes(usize),
+    /// flush once the oldest buffered message has waited this long
+    Linger(Duration),
+}
+
+/// a waker doing nothing, for writes attempted outside of a task
+struct NoopWake;
+
+impl Wake for NoopWake {
+    fn wake(self: Arc<Self>) {}
+}
 
-use crate::{AsyncDestination, AsyncFrameDestination, Framed, SyncDestination};
+fn noop_waker() -> &'static Waker {
+    static WAKER: OnceLock<Waker> = OnceLock::new();
+    WAKER.get_or_init(|| Waker::from(Arc::new(NoopWake)))
+}
+
+#[derive(Debug, Clone, Copy)]
+struct WriterOptions {
+    high_water_mark: usize,
+    low_water_mark: usize,
+    flush_policy: FlushPolicy,
+    write_timeout: Option<Duration>,
+    framing: FramingConfig,
+}
+
+impl Default for WriterOptions {
+    fn default() -> Self {
+        Self {
+            high_water_mark: usize::MAX,
+            low_water_mark: 0,
+            flush_policy: FlushPolicy::default(),
+            write_timeout: None,
+            framing: FramingConfig::default(),
+        }
+    }
+}
+
+/// Error of a flush or close that couldn't write anything for the write timeout, wrapped in an
+/// `io::Error` of `TimedOut` kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WriteTimeout(pub Duration);
+
+impl fmt::Display for WriteTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "nothing could be written for {:?}", self.0)
+    }
+}
+
+impl Error for WriteTimeout {}
 
 /// A warpper around an async sink that accepts, serializes, and sends prost-encoded values.
 #[derive(Debug)]
 pub struct AsyncProstWriter<W, T, D> {
     writer: W,
-    pub(crate) written: usize,
-    pub(crate) buffer: Vec<u8>,
+    buffer: WriteBuf,
+    batch: WriteBuf,
+    batch_count: usize,
+    options: WriterOptions,
+    draining: bool,
+    buffered_since: Option<Instant>,
+    linger: Option<Pin<Box<Sleep>>>,
+    write_deadline: Option<Pin<Box<Sleep>>>,
     from: PhantomData<T>,
     dest: PhantomData<D>,
 }
@@ -28,13 +117,67 @@ impl<W, T, D> AsyncProstWriter<W, T, D> {
     pub fn new(writer: W) -> Self {
         Self {
             writer,
-            written: 0,
-            buffer: Vec::new(),
+            buffer: WriteBuf::new(),
+            batch: WriteBuf::new(),
+            batch_count: 0,
+            options: WriterOptions::default(),
+            draining: false,
+            buffered_since: None,
+            linger: None,
+            write_deadline: None,
             from: PhantomData,
             dest: PhantomData,
         }
     }
 
+    /// Make `poll_ready` apply backpressure: once more than `high_water_mark` bytes are buffered,
+    /// it writes out the buffer and returns `Pending` until less than `low_water_mark` bytes are
+    /// left.
+    ///
+    /// By default the buffer is unbounded. A low water mark above the high one is lowered to it.
+    pub fn with_backpressure(mut self, high_water_mark: usize, low_water_mark: usize) -> Self {
+        self.set_backpressure(high_water_mark, low_water_mark);
+        self
+    }
+
+    /// Set the water marks of the buffer, see `with_backpressure`.
+    pub fn set_backpressure(&mut self, high_water_mark: usize, low_water_mark: usize) {
+        self.options.high_water_mark = high_water_mark;
+        self.options.low_water_mark = low_water_mark.min(high_water_mark);
+    }
+
+    /// Make the writer flush by itself according to the policy, see `FlushPolicy`.
+    pub fn with_flush_policy(mut self, policy: FlushPolicy) -> Self {
+        self.set_flush_policy(policy);
+        self
+    }
+
+    /// Set the flush policy of the writer, see `FlushPolicy`.
+    pub fn set_flush_policy(&mut self, policy: FlushPolicy) {
+        self.options.flush_policy = policy;
+        self.linger = None;
+    }
+
+    /// Make `flush` and `close` fail with a `WriteTimeout` error once nothing could be written for
+    /// the duration, e.g. because the peer stopped reading.
+    ///
+    /// The data not written yet stays buffered, flushing again resumes where it stopped.
+    pub fn with_write_timeout(mut self, timeout: Duration) -> Self {
+        self.set_write_timeout(Some(timeout));
+        self
+    }
+
+    /// Set the write timeout of the writer, `None` to wait forever, see `with_write_timeout`.
+    pub fn set_write_timeout(&mut self, timeout: Option<Duration>) {
+        self.options.write_timeout = timeout;
+        self.write_deadline = None;
+    }
+
+    /// returns the number of bytes buffered but not yet written
+    pub fn buffered(&self) -> usize {
+        self.buffer.len() + self.batch.len()
+    }
+
     /// Gets a reference to the underlying writer.
     pub fn get_ref(&self) -> &W {
         &self.writer
@@ -52,15 +195,363 @@ impl<W, T, D> AsyncProstWriter<W, T, D> {
         self.writer
     }
 
-    pub(crate) fn make_for<D2>(self) -> AsyncProstWriter<W, T, D2> {
+    /// Change the type of the values this writer accepts.
+    ///
+    /// The values buffered but not written yet go out as they were encoded, before anything sent
+    /// as `T2`. The destination, the framing and the other settings are kept, only the message
+    /// type changes.
+    pub fn retype<T2>(self) -> AsyncProstWriter<W, T2, D> {
+        AsyncProstWriter {
+            buffer: self.buffer,
+            batch: self.batch,
+            batch_count: self.batch_count,
+            writer: self.writer,
+            options: self.options,
+            draining: self.draining,
+            buffered_since: self.buffered_since,
+            linger: self.linger,
+            write_deadline: self.write_deadline,
+            from: PhantomData,
+            dest: self.dest,
+        }
+    }
+
+    /// switch to another destination, whose framing starts from the default limits
+    pub(crate) fn make_for<D2>(mut self) -> AsyncProstWriter<W, T, D2> {
+        // the values batched so far are sent as a batch, before anything written in the new mode
+        self.seal_batch();
+        let options = WriterOptions {
+            framing: FramingConfig::default(),
+            ..self.options
+        };
         AsyncProstWriter {
             buffer: self.buffer,
+            batch: self.batch,
+            batch_count: self.batch_count,
             writer: self.writer,
-            written: self.written,
+            options,
+            draining: self.draining,
+            buffered_since: self.buffered_since,
+            linger: self.linger,
+            write_deadline: self.write_deadline,
             from: self.from,
             dest: PhantomData,
         }
     }
+
+    /// steal the buffered state and the settings of this writer, leaving it empty
+    pub(crate) fn take_state(&mut self) -> AsyncProstWriter<(), T, D> {
+        let mut state = AsyncProstWriter::new(());
+        state.buffer = std::mem::take(&mut self.buffer);
+        state.batch = std::mem::take(&mut self.batch);
+        state.batch_count = std::mem::take(&mut self.batch_count);
+        state.options = self.options;
+        state.draining = std::mem::take(&mut self.draining);
+        state.buffered_since = self.buffered_since.take();
+        state.linger = self.linger.take();
+        state.write_deadline = self.write_deadline.take();
+        state
+    }
+
+    /// encode the item on its own, instead of appending it to the buffer
+    pub(crate) fn encode_item<I>(&mut self, item: I) -> Result<WriteBuf, io::Error>
+    where
+        Self: ProstWriterFor<I>,
+    {
+        let buffer = std::mem::take(&mut self.buffer);
+        // a message encoded on its own goes in a batch of its own
+        let res = self.append(item).map(|_| self.seal_batch());
+        let encoded = std::mem::replace(&mut self.buffer, buffer);
+        res.map(|_| encoded)
+    }
+
+    /// close the open batch, if any, and move it to the buffer
+    fn seal_batch(&mut self) {
+        if self.batch_count == 0 {
+            return;
+        }
+        self.buffer.put_u32(self.batch.len() as u32);
+        self.buffer.append(std::mem::take(&mut self.batch));
+        self.batch_count = 0;
+    }
+
+    /// set the limits of a batch, see `for_async_batched`
+    pub(crate) fn set_batch_limits(
+        &mut self,
+        max_bytes: usize,
+        max_count: usize,
+    ) -> Result<(), io::Error> {
+        let framing =
+            FramingConfig::from(AsyncBatchDestination).with_batch_limits(max_bytes, max_count);
+        framing.validate()?;
+        self.options.framing = framing;
+        Ok(())
+    }
+
+    /// set the framing used by the configured destinations
+    pub(crate) fn set_framing(&mut self, framing: FramingConfig) -> Result<(), io::Error> {
+        framing.validate()?;
+        self.options.framing = framing;
+        Ok(())
+    }
+
+    /// append already encoded bytes to the buffer
+    pub(crate) fn append_encoded(&mut self, encoded: WriteBuf) {
+        self.buffer.append(encoded);
+        if self.buffered_since.is_none() {
+            self.buffered_since = Some(Instant::now());
+        }
+    }
+
+    /// the water marks set by `with_backpressure`
+    pub(crate) fn water_marks(&self) -> (usize, usize) {
+        (self.options.high_water_mark, self.options.low_water_mark)
+    }
+
+    /// replace the underlying writer, keeping the buffered state and the settings
+    pub(crate) fn with_writer<W2>(self, writer: W2) -> AsyncProstWriter<W2, T, D> {
+        AsyncProstWriter {
+            writer,
+            buffer: self.buffer,
+            batch: self.batch,
+            batch_count: self.batch_count,
+            options: self.options,
+            draining: self.draining,
+            buffered_since: self.buffered_since,
+            linger: self.linger,
+            write_deadline: self.write_deadline,
+            from: self.from,
+            dest: self.dest,
+        }
+    }
+}
+
+impl<W, T, D> AsyncProstWriter<W, T, D>
+where
+    W: AsyncWrite + Unpin,
+{
+    /// write out the buffer until at most `target` bytes are left
+    pub(crate) fn poll_write_until(
+        &mut self,
+        cx: &mut Context<'_>,
+        target: usize,
+    ) -> Poll<io::Result<()>> {
+        self.seal_batch();
+        while self.buffered() > target {
+            let n = if self.writer.is_write_vectored() {
+                let mut slices = [IoSlice::new(&[]); MAX_IO_SLICES];
+                let cnt = self.buffer.io_slices(&mut slices);
+                ready!(Pin::new(&mut self.writer).poll_write_vectored(cx, &slices[..cnt]))?
+            } else {
+                ready!(Pin::new(&mut self.writer).poll_write(cx, self.buffer.front()))?
+            };
+            if n == 0 {
+                return Poll::Ready(Err(io::Error::from(io::ErrorKind::WriteZero)));
+            }
+            self.buffer.consume(n);
+        }
+
+        if self.buffer.is_empty() {
+            self.buffered_since = None;
+            self.linger = None;
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    /// write out everything and flush the underlying writer, without write timeout
+    pub(crate) fn poll_flush_untimed(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        // write stuff out if we need to
+        ready!(self.poll_write_until(cx, 0))?;
+        self.draining = false;
+
+        // we have to flush before we're really done
+        Pin::new(&mut self.writer).poll_flush(cx)
+    }
+
+    /// write out everything and flush the underlying writer
+    pub(crate) fn poll_flush_all(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let before = self.buffered();
+        let res = self.poll_flush_untimed(cx);
+        let progressed = self.buffered() != before;
+        self.poll_timed(cx, res, progressed)
+    }
+
+    /// write out everything, flush and shut down the underlying writer
+    pub(crate) fn poll_close_all(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let before = self.buffered();
+        let res = match self.poll_flush_untimed(cx) {
+            Poll::Ready(Ok(())) => Pin::new(&mut self.writer).poll_shutdown(cx),
+            other => other,
+        };
+        let progressed = self.buffered() != before;
+        self.poll_timed(cx, res, progressed)
+    }
+
+    /// apply the write timeout to the result of a flush or close
+    pub(crate) fn poll_timed(
+        &mut self,
+        cx: &mut Context<'_>,
+        res: Poll<io::Result<()>>,
+        progressed: bool,
+    ) -> Poll<io::Result<()>> {
+        let timeout = match (res, self.options.write_timeout) {
+            (Poll::Ready(res), _) => {
+                self.write_deadline = None;
+                return Poll::Ready(res);
+            }
+            (Poll::Pending, None) => return Poll::Pending,
+            (Poll::Pending, Some(timeout)) => timeout,
+        };
+
+        // the timer restarts whenever something could be written
+        let deadline = Instant::now() + timeout;
+        let sleep = self
+            .write_deadline
+            .get_or_insert_with(|| Box::pin(sleep_until(deadline)));
+        if progressed {
+            sleep.as_mut().reset(deadline);
+        }
+        ready!(sleep.as_mut().poll(cx));
+
+        self.write_deadline = None;
+        Poll::Ready(Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            WriteTimeout(timeout),
+        )))
+    }
+
+    /// Buffer a value the writer accepts besides `T`, like `SinkExt::feed`.
+    ///
+    /// Writers take `&T`, to send without cloning, and the value pre-encoded for their
+    /// destination: `EncodedMessage<T>` for `for_async`, `for_async_batched`, `for_configured`
+    /// and the sync destination, `EncodedFrame<T>` for the framed ones. The writer is a `Sink` of
+    /// `T` only, use `item_sink` to send the others with `SinkExt` or `StreamExt::forward`.
+    pub async fn feed_item<I>(&mut self, item: I) -> io::Result<()>
+    where
+        Self: ProstWriterFor<I>,
+    {
+        poll_fn(|cx| self.poll_ready_all(cx)).await?;
+        self.start_send_item(item)
+    }
+
+    /// Buffer a value the writer accepts besides `T` and flush, like `SinkExt::send`, see
+    /// `feed_item`.
+    pub async fn send_item<I>(&mut self, item: I) -> io::Result<()>
+    where
+        Self: ProstWriterFor<I>,
+    {
+        self.feed_item(item).await?;
+        poll_fn(|cx| self.poll_flush_all(cx)).await
+    }
+
+    /// Borrow the writer as a `Sink` of a value it accepts besides `T`, see `feed_item`.
+    ///
+    /// The item type is usually inferred, e.g. from the stream given to `StreamExt::forward`.
+    pub fn item_sink<I>(&mut self) -> ItemSink<'_, W, T, D, I>
+    where
+        Self: ProstWriterFor<I>,
+    {
+        ItemSink {
+            writer: self,
+            item: PhantomData,
+        }
+    }
+
+    /// apply the flush policy and the backpressure, ready once a value could be buffered
+    pub(crate) fn poll_ready_all(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if self.poll_flush_due(cx) {
+            ready!(self.poll_flush_all(cx))?;
+        }
+
+        if self.buffered() > self.options.high_water_mark {
+            self.draining = true;
+        }
+
+        if self.draining {
+            let low_water_mark = self.options.low_water_mark;
+            ready!(self.poll_write_until(cx, low_water_mark))?;
+            self.draining = false;
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    /// buffer a value, `poll_ready_all` shall be ready before
+    pub(crate) fn start_send_item<I>(&mut self, item: I) -> io::Result<()>
+    where
+        Self: ProstWriterFor<I>,
+    {
+        if self.buffer.is_empty() {
+            // NOTE: in theory we could have a short-circuit here that tries to have prost write
+            // directly into self.writer. this would be way more efficient in the common case as we
+            // don't have to do the extra buffering. the idea would be to serialize fist, and *if*
+            // it errors, see how many bytes were written, serialize again into a Vec, and then
+            // keep only the bytes following the number that were written in our buffer.
+            // unfortunately, prost will not tell us that number at the moment, and instead just
+            // fail.
+        }
+
+        self.append(item)?;
+        // a flush dropped while pending left its deadline behind, the next one starts afresh
+        self.write_deadline = None;
+        self.buffered_now()
+    }
+
+    /// Wait until the flush policy asks for a flush, then write out everything buffered.
+    ///
+    /// The policy is only applied when the writer is polled, so a lingering value stays buffered
+    /// until the next send. Await this next to the source of the values, e.g. in a `select!`
+    /// loop, to have it go out in time. It never completes with nothing buffered or with the
+    /// `Manual` policy, and the data not written yet stays buffered if it's dropped.
+    pub async fn flush_when_due(&mut self) -> io::Result<()> {
+        poll_fn(|cx| match self.poll_flush_due(cx) {
+            true => Poll::Ready(()),
+            false => Poll::Pending,
+        })
+        .await;
+        poll_fn(|cx| self.poll_flush_all(cx)).await
+    }
+
+    /// returns true if the flush policy asks for a flush, the linger timer wakes `cx` otherwise
+    fn poll_flush_due(&mut self, cx: &mut Context<'_>) -> bool {
+        let buffered = self.buffered();
+        if buffered == 0 {
+            return false;
+        }
+        match self.options.flush_policy {
+            FlushPolicy::Manual => false,
+            FlushPolicy::EveryMessage => true,
+            FlushPolicy::Bytes(n) => buffered >= n,
+            FlushPolicy::Linger(d) => {
+                let deadline = match self.buffered_since {
+                    Some(since) => since + d,
+                    None => return false,
+                };
+                if Instant::now() >= deadline {
+                    return true;
+                }
+                let sleep = self
+                    .linger
+                    .get_or_insert_with(|| Box::pin(sleep_until(deadline)));
+                sleep.as_mut().poll(cx).is_ready()
+            }
+        }
+    }
+
+    /// record the first buffered byte, and apply the flush policy as far as the underlying
+    /// writer accepts data without waiting
+    fn buffered_now(&mut self) -> io::Result<()> {
+        if self.buffered_since.is_none() {
+            self.buffered_since = Some(Instant::now());
+        }
+        let mut cx = Context::from_waker(noop_waker());
+        if self.poll_flush_due(&mut cx) {
+            if let Poll::Ready(Err(e)) = self.poll_flush_untimed(&mut cx) {
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
 }
 
 impl<W, T, D> Unpin for AsyncProstWriter<W, T, D> {}
@@ -86,10 +577,146 @@ impl<W, T> AsyncProstWriter<W, T, SyncDestination> {
         self.make_for()
     }
 
+    /// Make this writer pack the serialized values into batch frames of at most `max_bytes` bytes
+    /// (unless a single value is larger) and `max_count` values, each batch prefixed by its size.
+    ///
+    /// The open batch is sent on flush, so a batch may be smaller. Read it with a reader for
+    /// `AsyncBatchDestination`. Fails with `InvalidInput` if a limit is zero or `max_bytes`
+    /// doesn't fit in the size prefix, see `FramingConfig::validate`.
+    pub fn for_async_batched(
+        self,
+        max_bytes: usize,
+        max_count: usize,
+    ) -> Result<AsyncProstWriter<W, T, AsyncBatchDestination>, io::Error> {
+        let mut writer = self.make_for();
+        writer.set_batch_limits(max_bytes, max_count)?;
+        Ok(writer)
+    }
+
     /// make this writer include the serialized data's header and body size before serialized value
     pub fn for_async_framed(self) -> AsyncProstWriter<W, T, AsyncFrameDestination> {
         self.make_for()
     }
+
+    /// make this writer include the serialized data's header and body size, and the frame flags
+    /// before serialized value
+    pub fn for_async_framed_with_flags(
+        self,
+    ) -> AsyncProstWriter<W, T, AsyncFlaggedFrameDestination> {
+        self.make_for()
+    }
+
+    /// Make this writer frame prost values as set by `framing`, chosen at runtime.
+    ///
+    /// Fails if the limits are invalid, see `FramingConfig::validate`. A `Prefix::Frame` framing
+    /// makes every write fail, use `for_configured_framed` to send frames.
+    pub fn for_configured(
+        self,
+        framing: FramingConfig,
+    ) -> Result<AsyncProstWriter<W, T, ConfiguredDestination>, io::Error> {
+        let mut writer = self.make_for();
+        writer.set_framing(framing)?;
+        Ok(writer)
+    }
+
+    /// Make this writer frame `Framed` values as set by `framing`, chosen at runtime.
+    ///
+    /// Fails if the limits are invalid, see `FramingConfig::validate`. Any prefix but
+    /// `Prefix::Frame` makes every write fail.
+    pub fn for_configured_framed(
+        self,
+        framing: FramingConfig,
+    ) -> Result<AsyncProstWriter<W, T, ConfiguredFrameDestination>, io::Error> {
+        let mut writer = self.make_for();
+        writer.set_framing(framing)?;
+        Ok(writer)
+    }
+}
+
+impl<W, T, D> AsyncProstWriter<W, T, D> {
+    /// append a frame, with its flags if `with_flags`
+    fn append_frame(&mut self, item: T, with_flags: bool) -> Result<(), io::Error>
+    where
+        T: Framed,
+    {
+        let flags = with_flags.then(|| item.flags());
+        FrameLen::of(&item)?.put_prefix(&mut self.buffer, flags)?;
+        item.encode_into(&mut self.buffer)
+    }
+
+    /// append a borrowed frame, with its flags if `with_flags`
+    fn append_frame_ref(&mut self, item: &T, with_flags: bool) -> Result<(), io::Error>
+    where
+        T: Framed,
+    {
+        let flags = with_flags.then(|| item.flags());
+        FrameLen::of(item)?.put_prefix(&mut self.buffer, flags)?;
+        item.encode(&mut self.buffer)
+    }
+
+    /// append an already encoded frame, with its flags if `with_flags`
+    fn append_encoded_frame(
+        &mut self,
+        item: EncodedFrame<T>,
+        with_flags: bool,
+    ) -> Result<(), io::Error> {
+        let flags = with_flags.then(|| item.flags());
+        item.frame_len().put_prefix(&mut self.buffer, flags)?;
+        self.buffer.put_bytes(item.bytes().clone());
+        Ok(())
+    }
+
+    /// append a value prefixed by its size
+    fn append_delimited(&mut self, item: &T) -> Result<(), io::Error>
+    where
+        T: Message,
+    {
+        let size = item.encoded_len() as u32;
+
+        self.buffer.put_u32(size);
+        item.encode(&mut self.buffer)?;
+        Ok(())
+    }
+
+    /// append a value to the open batch, with `encode` writing it length-delimited
+    fn append_batched<F>(&mut self, len: usize, encode: F) -> Result<(), io::Error>
+    where
+        F: FnOnce(&mut WriteBuf) -> Result<(), io::Error>,
+    {
+        let delimited_len = encoded_len_varint(len as u64) + len;
+        let framing = self.options.framing;
+        if self.batch_count > 0 && self.batch.len() + delimited_len > framing.max_batch_bytes {
+            self.seal_batch();
+        }
+
+        encode(&mut self.batch)?;
+        self.batch_count += 1;
+        if self.batch_count >= framing.max_batch_count
+            || self.batch.len() >= framing.max_batch_bytes
+        {
+            self.seal_batch();
+        }
+        Ok(())
+    }
+
+    /// append an encoded value to the open batch
+    fn append_batched_encoded(&mut self, item: EncodedMessage<T>) -> Result<(), io::Error> {
+        self.append_batched(item.len(), |batch| {
+            encode_varint(item.len() as u64, batch);
+            batch.put_bytes(item.bytes().clone());
+            Ok(())
+        })
+    }
+
+    /// append a value to the open batch
+    fn append_batched_ref(&mut self, item: &T) -> Result<(), io::Error>
+    where
+        T: Message,
+    {
+        self.append_batched(item.encoded_len(), |batch| {
+            Ok(item.encode_length_delimited(batch)?)
+        })
+    }
 }
 
 #[doc(hidden)]
@@ -99,34 +726,180 @@ pub trait ProstWriterFor<T> {
 
 impl<W, F: Framed> ProstWriterFor<F> for AsyncProstWriter<W, F, AsyncFrameDestination> {
     fn append(&mut self, item: F) -> Result<(), io::Error> {
-        let size = item.encoded_len();
-        self.buffer.write_u32::<NetworkEndian>(size)?;
-        item.encode(&mut self.buffer)?;
-        Ok(())
+        self.append_frame(item, false)
+    }
+}
+
+impl<'a, W, F: Framed> ProstWriterFor<&'a F> for AsyncProstWriter<W, F, AsyncFrameDestination> {
+    fn append(&mut self, item: &'a F) -> Result<(), io::Error> {
+        self.append_frame_ref(item, false)
+    }
+}
+
+impl<W, F> ProstWriterFor<EncodedFrame<F>> for AsyncProstWriter<W, F, AsyncFrameDestination> {
+    fn append(&mut self, item: EncodedFrame<F>) -> Result<(), io::Error> {
+        self.append_encoded_frame(item, false)
+    }
+}
+
+impl<W, F: Framed> ProstWriterFor<F> for AsyncProstWriter<W, F, AsyncFlaggedFrameDestination> {
+    fn append(&mut self, item: F) -> Result<(), io::Error> {
+        self.append_frame(item, true)
+    }
+}
+
+impl<'a, W, F: Framed> ProstWriterFor<&'a F>
+    for AsyncProstWriter<W, F, AsyncFlaggedFrameDestination>
+{
+    fn append(&mut self, item: &'a F) -> Result<(), io::Error> {
+        self.append_frame_ref(item, true)
+    }
+}
+
+impl<W, F> ProstWriterFor<EncodedFrame<F>>
+    for AsyncProstWriter<W, F, AsyncFlaggedFrameDestination>
+{
+    fn append(&mut self, item: EncodedFrame<F>) -> Result<(), io::Error> {
+        self.append_encoded_frame(item, true)
     }
 }
 
 impl<W, T: Message> ProstWriterFor<T> for AsyncProstWriter<W, T, AsyncDestination> {
     fn append(&mut self, item: T) -> Result<(), io::Error> {
-        let size = item.encoded_len() as u32;
+        self.append(&item)
+    }
+}
 
-        self.buffer.write_u32::<NetworkEndian>(size)?;
-        item.encode(&mut self.buffer)?;
+impl<'a, W, T: Message> ProstWriterFor<&'a T> for AsyncProstWriter<W, T, AsyncDestination> {
+    fn append(&mut self, item: &'a T) -> Result<(), io::Error> {
+        self.append_delimited(item)
+    }
+}
+
+impl<W, T> ProstWriterFor<EncodedMessage<T>> for AsyncProstWriter<W, T, AsyncDestination> {
+    fn append(&mut self, item: EncodedMessage<T>) -> Result<(), io::Error> {
+        self.buffer.put_u32(item.len() as u32);
+        self.buffer.put_bytes(item.bytes().clone());
         Ok(())
     }
 }
 
+impl<W, T: Message> ProstWriterFor<T> for AsyncProstWriter<W, T, AsyncBatchDestination> {
+    fn append(&mut self, item: T) -> Result<(), io::Error> {
+        self.append(&item)
+    }
+}
+
+impl<'a, W, T: Message> ProstWriterFor<&'a T> for AsyncProstWriter<W, T, AsyncBatchDestination> {
+    fn append(&mut self, item: &'a T) -> Result<(), io::Error> {
+        self.append_batched_ref(item)
+    }
+}
+
+impl<W, T> ProstWriterFor<EncodedMessage<T>> for AsyncProstWriter<W, T, AsyncBatchDestination> {
+    fn append(&mut self, item: EncodedMessage<T>) -> Result<(), io::Error> {
+        self.append_batched_encoded(item)
+    }
+}
+
 // FIXME: why do we need this impl without writing the size?
 impl<W, T> ProstWriterFor<T> for AsyncProstWriter<W, T, SyncDestination>
 where
     T: Message,
 {
     fn append(&mut self, item: T) -> Result<(), io::Error> {
+        self.append(&item)
+    }
+}
+
+impl<'a, W, T> ProstWriterFor<&'a T> for AsyncProstWriter<W, T, SyncDestination>
+where
+    T: Message,
+{
+    fn append(&mut self, item: &'a T) -> Result<(), io::Error> {
         item.encode(&mut self.buffer)?;
         Ok(())
     }
 }
 
+impl<W, T> ProstWriterFor<EncodedMessage<T>> for AsyncProstWriter<W, T, SyncDestination> {
+    fn append(&mut self, item: EncodedMessage<T>) -> Result<(), io::Error> {
+        self.buffer.put_bytes(item.bytes().clone());
+        Ok(())
+    }
+}
+
+impl<W, T: Message> ProstWriterFor<T> for AsyncProstWriter<W, T, ConfiguredDestination> {
+    fn append(&mut self, item: T) -> Result<(), io::Error> {
+        self.append(&item)
+    }
+}
+
+impl<'a, W, T: Message> ProstWriterFor<&'a T> for AsyncProstWriter<W, T, ConfiguredDestination> {
+    fn append(&mut self, item: &'a T) -> Result<(), io::Error> {
+        let framing = self.options.framing;
+        framing.check_len(item.encoded_len())?;
+        match framing.prefix {
+            Prefix::None => Ok(item.encode(&mut self.buffer)?),
+            Prefix::Length => self.append_delimited(item),
+            Prefix::Batch => self.append_batched_ref(item),
+            Prefix::Frame => Err(framing.unsupported()),
+        }
+    }
+}
+
+impl<W, T> ProstWriterFor<EncodedMessage<T>> for AsyncProstWriter<W, T, ConfiguredDestination> {
+    fn append(&mut self, item: EncodedMessage<T>) -> Result<(), io::Error> {
+        let framing = self.options.framing;
+        framing.check_len(item.len())?;
+        match framing.prefix {
+            Prefix::None => self.buffer.put_bytes(item.bytes().clone()),
+            Prefix::Length => {
+                self.buffer.put_u32(item.len() as u32);
+                self.buffer.put_bytes(item.bytes().clone());
+            }
+            Prefix::Batch => return self.append_batched_encoded(item),
+            Prefix::Frame => return Err(framing.unsupported()),
+        }
+        Ok(())
+    }
+}
+
+impl<W, F: Framed> ProstWriterFor<F> for AsyncProstWriter<W, F, ConfiguredFrameDestination> {
+    fn append(&mut self, item: F) -> Result<(), io::Error> {
+        let framing = self.options.framing;
+        if framing.prefix != Prefix::Frame {
+            return Err(framing.unsupported());
+        }
+        framing.check_len(item.header_len()? + item.body_len()?)?;
+        self.append_frame(item, framing.flags)
+    }
+}
+
+impl<'a, W, F: Framed> ProstWriterFor<&'a F>
+    for AsyncProstWriter<W, F, ConfiguredFrameDestination>
+{
+    fn append(&mut self, item: &'a F) -> Result<(), io::Error> {
+        let framing = self.options.framing;
+        if framing.prefix != Prefix::Frame {
+            return Err(framing.unsupported());
+        }
+        framing.check_len(item.header_len()? + item.body_len()?)?;
+        self.append_frame_ref(item, framing.flags)
+    }
+}
+
+impl<W, F> ProstWriterFor<EncodedFrame<F>> for AsyncProstWriter<W, F, ConfiguredFrameDestination> {
+    fn append(&mut self, item: EncodedFrame<F>) -> Result<(), io::Error> {
+        let framing = self.options.framing;
+        if framing.prefix != Prefix::Frame {
+            return Err(framing.unsupported());
+        }
+        framing.check_len(item.len())?;
+        self.append_encoded_frame(item, framing.flags)
+    }
+}
+
 impl<W, T, D> Sink<T> for AsyncProstWriter<W, T, D>
 where
     W: AsyncWrite + Unpin,
@@ -134,42 +907,51 @@ where
 {
     type Error = io::Error;
 
-    fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        Poll::Ready(Ok(()))
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().poll_ready_all(cx)
     }
 
-    fn start_send(mut self: Pin<&mut Self>, item: T) -> Result<(), Self::Error> {
-        if self.buffer.is_empty() {
-            // NOTE: in theory we could have a short-circuit here that tries to have prost write
-            // directly into self.writer. this would be way more efficient in the common case as we
-            // don't have to do the extra buffering. the idea would be to serialize fist, and *if*
-            // it errors, see how many bytes were written, serialize again into a Vec, and then
-            // keep only the bytes following the number that were written in our buffer.
-            // unfortunately, prost will not tell us that number at the moment, and instead just
-            // fail.
-        }
-
-        self.append(item)
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), Self::Error> {
+        self.get_mut().start_send_item(item)
     }
 
     fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        let this = self.get_mut();
+        self.get_mut().poll_flush_all(cx)
+    }
 
-        // write stuff out if we need to
-        while this.written != this.buffer.len() {
-            let n =
-                ready!(Pin::new(&mut this.writer).poll_write(cx, &this.buffer[this.written..]))?;
-            this.written += n;
-        }
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().poll_close_all(cx)
+    }
+}
 
-        // we have to flush before we're really done
-        this.buffer.clear();
-        this.written = 0;
-        Pin::new(&mut this.writer).poll_flush(cx)
+/// A `Sink` of a value an `AsyncProstWriter` accepts besides its item type, like `&T` or a
+/// pre-encoded value, made by `AsyncProstWriter::item_sink`.
+#[derive(Debug)]
+pub struct ItemSink<'a, W, T, D, I> {
+    writer: &'a mut AsyncProstWriter<W, T, D>,
+    item: PhantomData<fn(I)>,
+}
+
+impl<W, T, D, I> Sink<I> for ItemSink<'_, W, T, D, I>
+where
+    W: AsyncWrite + Unpin,
+    AsyncProstWriter<W, T, D>: ProstWriterFor<I>,
+{
+    type Error = io::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().writer.poll_ready_all(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: I) -> Result<(), Self::Error> {
+        self.get_mut().writer.start_send_item(item)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().writer.poll_flush_all(cx)
     }
 
-    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        ready!(self.as_mut().poll_flush(cx))?;
-        Pin::new(&mut self.writer).poll_shutdown(cx)
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().writer.poll_close_all(cx)
     }
 }
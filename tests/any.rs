@@ -0,0 +1,78 @@
+#![cfg(feature = "any")]
+
+use std::{any::Any as StdAny, io, sync::Arc};
+
+use futures::prelude::*;
+use prost::Message;
+use prost_types::Any;
+
+use async_prost::*;
+use tokio::io::duplex;
+
+const PING_URL: &str = "type.googleapis.com/test.Ping";
+const PONG_URL: &str = "type.googleapis.com/test.Pong";
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Ping {
+    #[prost(uint64, tag = "1")]
+    pub seq: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Pong {
+    #[prost(string, tag = "1")]
+    pub reply: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Event {
+    Ping(Ping),
+    Pong(Pong),
+}
+
+type Boxed = Box<dyn StdAny + Send + Sync>;
+
+#[tokio::test]
+async fn any_reader_should_dispatch_by_type_url() {
+    let (tx, rx) = duplex(4096);
+    let mut tx = AsyncProstWriter::<_, Any, _>::from(tx).for_async();
+
+    let registry = AnyRegistry::new()
+        .register(PING_URL.to_string(), Event::Ping)
+        .register(PONG_URL.to_string(), Event::Pong);
+    let mut rx =
+        AsyncProstReader::<_, Any, AsyncDestination>::from(rx).dispatch_any(Arc::new(registry));
+
+    let ping = Ping { seq: 1 };
+    let pong = Pong {
+        reply: "hello".to_string(),
+    };
+    tx.send(pack_any(PING_URL, &ping)).await.unwrap();
+    tx.send(pack_any(PONG_URL, &pong)).await.unwrap();
+    tx.send(pack_any("type.googleapis.com/test.Unknown", &ping))
+        .await
+        .unwrap();
+
+    assert_eq!(rx.next().await.unwrap().unwrap(), Event::Ping(ping));
+    assert_eq!(rx.next().await.unwrap().unwrap(), Event::Pong(pong));
+    let err = rx.next().await.unwrap().unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    let err = err
+        .into_inner()
+        .unwrap()
+        .downcast::<UnknownTypeUrl>()
+        .unwrap();
+    assert_eq!(err.0, "type.googleapis.com/test.Unknown");
+}
+
+#[test]
+fn any_registry_should_support_boxed_values() {
+    let registry: AnyRegistry<Boxed> = AnyRegistry::new()
+        .register(PING_URL.to_string(), |m: Ping| Box::new(m) as Boxed)
+        .register(PONG_URL.to_string(), |m: Pong| Box::new(m) as Boxed);
+
+    let any = pack_any(PING_URL, &Ping { seq: 42 });
+    let value = unpack_any(&registry, &any).unwrap();
+    assert_eq!(value.downcast_ref::<Ping>().unwrap().seq, 42);
+    assert!(value.downcast_ref::<Pong>().is_none());
+}
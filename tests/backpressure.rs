@@ -0,0 +1,76 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures::prelude::*;
+use prost::Message;
+
+use async_prost::*;
+use tokio::{io::duplex, time::timeout};
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Event {
+    #[prost(bytes = "bytes", tag = "1")]
+    pub data: Bytes,
+}
+
+#[tokio::test]
+async fn poll_ready_should_apply_backpressure() {
+    let (a, b) = duplex(64);
+    let mut tx = AsyncProstWriter::<_, Event, _>::from(a)
+        .for_async()
+        .with_backpressure(1024, 256);
+    let mut rx = AsyncProstReader::<_, Event, AsyncDestination>::from(b);
+
+    let event = Event {
+        data: Bytes::from(vec![1u8; 100]),
+    };
+    let size = event.encoded_len() + 4;
+
+    // the peer doesn't read, so feeding must eventually block
+    let mut sent = 0;
+    loop {
+        match timeout(Duration::from_millis(50), tx.feed(event.clone())).await {
+            Ok(res) => res.unwrap(),
+            Err(_) => break,
+        }
+        sent += 1;
+        assert!(tx.buffered() <= 1024 + size);
+        assert!(sent < 1000, "poll_ready never applied backpressure");
+    }
+
+    // once the peer reads, everything shall go through
+    let reader = tokio::spawn(async move {
+        for _ in 0..sent + 10 {
+            rx.next().await.unwrap().unwrap();
+        }
+    });
+    for _ in 0..10 {
+        tx.feed(event.clone()).await.unwrap();
+    }
+    tx.flush().await.unwrap();
+    reader.await.unwrap();
+}
+
+#[tokio::test]
+async fn low_water_mark_above_high_should_be_lowered() {
+    let (a, b) = duplex(64);
+    let mut tx = AsyncProstWriter::<_, Event, _>::from(a)
+        .for_async()
+        .with_backpressure(256, 4096);
+    let mut rx = AsyncProstReader::<_, Event, AsyncDestination>::from(b);
+
+    let event = Event {
+        data: Bytes::from(vec![1u8; 100]),
+    };
+    let reader = tokio::spawn(async move {
+        for _ in 0..20 {
+            rx.next().await.unwrap().unwrap();
+        }
+    });
+    for _ in 0..20 {
+        tx.feed(event.clone()).await.unwrap();
+        assert!(tx.buffered() <= 256 + event.encoded_len() + 4);
+    }
+    tx.flush().await.unwrap();
+    reader.await.unwrap();
+}
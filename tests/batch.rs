@@ -0,0 +1,133 @@
+use bytes::Bytes;
+use futures::prelude::*;
+use prost::Message;
+
+use async_prost::*;
+use tokio::io::{duplex, AsyncReadExt};
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Event {
+    #[prost(uint64, tag = "1")]
+    pub seq: u64,
+    #[prost(bytes = "bytes", tag = "2")]
+    pub data: Bytes,
+}
+
+fn event(seq: u64) -> Event {
+    Event {
+        seq,
+        data: Bytes::from_static(b"tiny"),
+    }
+}
+
+/// count the batch frames on the wire
+fn batch_sizes(mut wire: &[u8]) -> Vec<usize> {
+    let mut sizes = Vec::new();
+    while !wire.is_empty() {
+        let size = u32::from_be_bytes([wire[0], wire[1], wire[2], wire[3]]) as usize;
+        sizes.push(size);
+        wire = &wire[4 + size..];
+    }
+    sizes
+}
+
+#[tokio::test]
+async fn batches_should_be_limited_by_count_and_size() {
+    let (a, mut b) = duplex(64 * 1024);
+    let mut tx = AsyncProstWriter::<_, Event, _>::from(a)
+        .for_async_batched(1024, 4)
+        .unwrap();
+    for seq in 1..=10 {
+        tx.feed(event(seq)).await.unwrap();
+    }
+    tx.close().await.unwrap();
+    drop(tx);
+    let mut wire = Vec::new();
+    b.read_to_end(&mut wire).await.unwrap();
+
+    // 4 + 4 + the open batch sent on flush
+    let delimited = event(1).encoded_len() + 1;
+    assert_eq!(
+        batch_sizes(&wire),
+        vec![4 * delimited, 4 * delimited, 2 * delimited]
+    );
+
+    let (a, mut b) = duplex(64 * 1024);
+    let mut tx = AsyncProstWriter::<_, Event, _>::from(a)
+        .for_async_batched(3 * delimited, 100)
+        .unwrap();
+    for seq in 1..=7 {
+        tx.feed(event(seq)).await.unwrap();
+    }
+    tx.close().await.unwrap();
+    drop(tx);
+    let mut wire = Vec::new();
+    b.read_to_end(&mut wire).await.unwrap();
+    assert_eq!(
+        batch_sizes(&wire),
+        vec![3 * delimited, 3 * delimited, delimited]
+    );
+}
+
+#[tokio::test]
+async fn batched_reader_should_yield_one_message_at_a_time() {
+    // smaller than a batch, the sender waits for the reader
+    let (tx, rx) = duplex(1024);
+    let mut tx = AsyncProstWriter::<_, Event, _>::from(tx)
+        .for_async_batched(4096, 16)
+        .unwrap();
+    let mut rx = AsyncProstReader::<_, Event, AsyncBatchDestination>::from(rx);
+
+    let n = 1000;
+    let sender = tokio::spawn(async move {
+        for seq in 0..n {
+            tx.feed(event(seq)).await.unwrap();
+        }
+        tx.close().await.unwrap();
+    });
+    for seq in 0..n {
+        assert_eq!(rx.next().await.unwrap().unwrap(), event(seq));
+    }
+    assert!(rx.next().await.is_none());
+    sender.await.unwrap();
+}
+
+#[tokio::test]
+async fn batched_stream_should_work() {
+    let (a, b) = duplex(4096);
+    let mut client = AsyncProstStream::<_, Event, Event, _>::from(a)
+        .for_async_batched(512, 8)
+        .unwrap();
+    let mut server = AsyncProstStream::<_, Event, Event, _>::from(b)
+        .for_async_batched(512, 8)
+        .unwrap();
+
+    for seq in 0..20 {
+        client.feed(event(seq)).await.unwrap();
+    }
+    client.flush().await.unwrap();
+    for seq in 0..20 {
+        let got = server.next().await.unwrap().unwrap();
+        assert_eq!(got.seq, seq);
+        server.feed(got).await.unwrap();
+    }
+    server.flush().await.unwrap();
+    for seq in 0..20 {
+        assert_eq!(client.next().await.unwrap().unwrap(), event(seq));
+    }
+}
+
+#[test]
+fn invalid_batch_limits_should_fail() {
+    for (max_bytes, max_count) in [(512, 0), (0, 8)] {
+        let (a, b) = duplex(64);
+        let err = AsyncProstWriter::<_, Event, _>::from(a)
+            .for_async_batched(max_bytes, max_count)
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        let err = AsyncProstStream::<_, Event, Event, _>::from(b)
+            .for_async_batched(max_bytes, max_count)
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    }
+}
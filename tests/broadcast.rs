@@ -0,0 +1,118 @@
+use std::time::Duration;
+
+use futures::prelude::*;
+
+use async_prost::*;
+use tokio::{
+    io::{duplex, DuplexStream},
+    time::timeout,
+};
+
+mod common;
+use common::*;
+
+fn event(seq: u64) -> Event {
+    sized_event(seq, 100)
+}
+
+type Writer = AsyncProstWriter<DuplexStream, Event, AsyncDestination>;
+type Reader = AsyncProstReader<DuplexStream, Event, AsyncDestination>;
+
+fn peer(size: usize) -> (Writer, Reader) {
+    let (a, b) = duplex(size);
+    (
+        AsyncProstWriter::from(a).for_async(),
+        AsyncProstReader::from(b),
+    )
+}
+
+#[tokio::test]
+async fn broadcaster_should_send_to_all_subscribers() {
+    let mut hub = Broadcaster::new();
+    let (tx1, mut rx1) = peer(4096);
+    let (tx2, mut rx2) = peer(4096);
+    let id1 = hub.subscribe(tx1, SlowPeerPolicy::Block);
+    hub.subscribe(tx2, SlowPeerPolicy::Block);
+    assert_eq!(hub.len(), 2);
+
+    hub.send(event(0)).await.unwrap();
+    assert_eq!(rx1.next().await.unwrap().unwrap(), event(0));
+    assert_eq!(rx2.next().await.unwrap().unwrap(), event(0));
+
+    // subscribers come and go at runtime
+    assert!(hub.unsubscribe(id1).is_some());
+    assert!(!hub.contains(id1));
+    let (tx3, mut rx3) = peer(4096);
+    hub.subscribe(tx3, SlowPeerPolicy::Block);
+
+    hub.send(event(1)).await.unwrap();
+    assert_eq!(rx2.next().await.unwrap().unwrap(), event(1));
+    assert_eq!(rx3.next().await.unwrap().unwrap(), event(1));
+    drop(hub);
+    assert!(rx1.next().await.is_none());
+}
+
+#[tokio::test]
+async fn slow_peers_should_follow_their_policy() {
+    let mut hub = Broadcaster::with_capacity(4);
+    let (fast, mut fast_rx) = peer(64 * 1024);
+    let (drop_oldest, _drop_oldest_rx) = peer(64);
+    let (disconnect, _disconnect_rx) = peer(64);
+    hub.subscribe(fast, SlowPeerPolicy::Block);
+    let drop_oldest = hub.subscribe(drop_oldest, SlowPeerPolicy::DropOldest);
+    let disconnect = hub.subscribe(disconnect, SlowPeerPolicy::Disconnect);
+
+    for seq in 0..20 {
+        hub.feed(event(seq)).await.unwrap();
+        assert!(hub.queued(drop_oldest).unwrap() <= 4);
+    }
+    hub.flush().now_or_never();
+
+    // the fast peer got everything
+    for seq in 0..20 {
+        assert_eq!(fast_rx.next().await.unwrap().unwrap(), event(seq));
+    }
+    assert!(hub.contains(drop_oldest));
+    assert!(!hub.contains(disconnect));
+    let disconnected = hub.take_disconnected();
+    assert_eq!(disconnected.len(), 1);
+    assert_eq!(disconnected[0].0, disconnect);
+}
+
+#[tokio::test]
+async fn blocking_slow_peer_should_hold_back_the_broadcast() {
+    let mut hub = Broadcaster::with_capacity(4);
+    let (slow, mut slow_rx) = peer(64);
+    hub.subscribe(slow, SlowPeerPolicy::Block);
+
+    let mut sent = 0;
+    while timeout(Duration::from_millis(50), hub.feed(event(sent)))
+        .await
+        .is_ok()
+    {
+        sent += 1;
+        assert!(sent < 100, "broadcast was never held back");
+    }
+
+    // once the peer reads, the broadcast goes on
+    let reader = tokio::spawn(async move {
+        for seq in 0..=sent {
+            assert_eq!(slow_rx.next().await.unwrap().unwrap(), event(seq));
+        }
+    });
+    hub.send(event(sent)).await.unwrap();
+    reader.await.unwrap();
+}
+
+#[tokio::test]
+async fn zero_capacity_should_queue_one_message() {
+    let mut hub = Broadcaster::with_capacity(0);
+    let (slow, _slow_rx) = peer(64);
+    let slow = hub.subscribe(slow, SlowPeerPolicy::DropOldest);
+
+    for seq in 0..20 {
+        hub.feed(event(seq)).await.unwrap();
+        assert!(hub.queued(slow).unwrap() <= 1);
+    }
+    assert!(hub.contains(slow));
+}
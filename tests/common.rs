@@ -1,3 +1,9 @@
+// shared by the integration tests, each uses only some of it
+#![allow(dead_code)]
+
+use bytes::Bytes;
+use prost::Message;
+
 pub struct PanicError;
 use std::fmt;
 impl<E> From<E> for PanicError
@@ -16,3 +22,27 @@ pub fn unwrap<T>(r: Result<T, PanicError>) -> T {
         unreachable!();
     }
 }
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Event {
+    #[prost(uint64, tag = "1")]
+    pub seq: u64,
+    #[prost(bytes = "bytes", tag = "2")]
+    pub data: Bytes,
+}
+
+/// an event with a short payload
+pub fn event(seq: u64) -> Event {
+    Event {
+        seq,
+        data: Bytes::from_static(b"hello"),
+    }
+}
+
+/// an event with a payload of `size` bytes
+pub fn sized_event(seq: u64, size: usize) -> Event {
+    Event {
+        seq,
+        data: Bytes::from(vec![0u8; size]),
+    }
+}
@@ -0,0 +1,78 @@
+use async_prost::*;
+use async_prost_derive::{Framed, ShallDecodeBody};
+use bytes::Bytes;
+use either::Either;
+use prost::Message;
+
+#[derive(Clone, PartialEq, Message, ShallDecodeBody)]
+#[decode_body(field = "kind", matches = "1 | 2")]
+pub struct KindHeader {
+    #[prost(uint32, tag = "1")]
+    kind: u32,
+}
+
+#[derive(Clone, PartialEq, Message, ShallDecodeBody)]
+#[decode_body(with = "is_even")]
+pub struct TagHeader {
+    #[prost(uint64, tag = "1")]
+    tag: u64,
+}
+
+fn is_even(header: &TagHeader) -> bool {
+    header.tag % 2 == 0
+}
+
+#[derive(Clone, PartialEq, Message, ShallDecodeBody)]
+pub struct PlainHeader {
+    #[prost(uint64, tag = "1")]
+    tag: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Body {
+    #[prost(bytes = "bytes", tag = "1")]
+    pub data: Bytes,
+}
+
+#[derive(Debug, Default, Framed)]
+struct KindFrame {
+    inner: Frame<KindHeader, Body>,
+}
+
+#[test]
+fn shall_decode_body_derive_should_work() {
+    assert!(KindHeader { kind: 1 }.shall_decode_body());
+    assert!(KindHeader { kind: 2 }.shall_decode_body());
+    assert!(!KindHeader { kind: 3 }.shall_decode_body());
+
+    assert!(TagHeader { tag: 2 }.shall_decode_body());
+    assert!(!TagHeader { tag: 3 }.shall_decode_body());
+
+    assert!(PlainHeader { tag: 3 }.shall_decode_body());
+}
+
+#[test]
+fn framed_derive_should_delegate_to_inner() {
+    let body = Body {
+        data: Bytes::from_static(b"hello"),
+    };
+    for (kind, decoded) in [(1, true), (3, false)] {
+        let header = KindHeader { kind };
+        let frame = KindFrame {
+            inner: Frame {
+                header: Some(header.clone()),
+                body: Some(Either::Right(body.clone())),
+                ..Default::default()
+            },
+        };
+        let header_len = frame.header_len().unwrap();
+        assert_eq!(header_len, frame.inner.header_len().unwrap());
+        assert_eq!(frame.body_len().unwrap(), frame.inner.body_len().unwrap());
+
+        let mut buf = Vec::new();
+        frame.encode(&mut buf).unwrap();
+        let got = KindFrame::decode(&buf, header_len).unwrap();
+        assert_eq!(got.inner.header, Some(header));
+        assert_eq!(got.inner.body.unwrap().is_right(), decoded);
+    }
+}
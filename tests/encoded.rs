@@ -0,0 +1,153 @@
+use bytes::Bytes;
+use either::Either;
+use futures::prelude::*;
+use prost::Message;
+
+use async_prost::*;
+use tokio::io::{duplex, AsyncReadExt};
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Header {
+    #[prost(uint64, tag = "1")]
+    tag: u64,
+}
+
+impl ShallDecodeBody for Header {
+    fn shall_decode_body(&self) -> bool {
+        true
+    }
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Event {
+    #[prost(bytes = "bytes", tag = "1")]
+    pub data: Bytes,
+}
+
+type EventFrame = Frame<Header, Event>;
+
+fn event() -> Event {
+    Event {
+        data: Bytes::from(vec![7u8; 1000]),
+    }
+}
+
+#[tokio::test]
+async fn encoded_message_should_be_sent_to_many_writers() {
+    let encoded = EncodedMessage::new(&event());
+    assert_eq!(encoded.as_bytes(), event().encode_to_vec());
+
+    let mut readers = Vec::new();
+    let mut writers = Vec::new();
+    for _ in 0..3 {
+        let (a, b) = duplex(4096);
+        writers.push(AsyncProstWriter::<_, Event, _>::from(a).for_async());
+        readers.push(AsyncProstReader::<_, Event, AsyncDestination>::from(b));
+    }
+
+    for tx in writers.iter_mut() {
+        tx.send_item(encoded.clone()).await.unwrap();
+    }
+    for rx in readers.iter_mut() {
+        assert_eq!(rx.next().await.unwrap().unwrap(), event());
+    }
+}
+
+#[tokio::test]
+async fn messages_should_be_sent_by_reference() {
+    let (a, b) = duplex(4096);
+    let mut tx = AsyncProstWriter::<_, Event, _>::from(a).for_async();
+    let mut rx = AsyncProstReader::<_, Event, AsyncDestination>::from(b);
+
+    let msg = event();
+    tx.feed_item(&msg).await.unwrap();
+    tx.feed(msg.clone()).await.unwrap();
+    // the writer is a sink of `Event` only, so `SinkExt::flush` needs no annotation
+    tx.flush().await.unwrap();
+    assert_eq!(rx.next().await.unwrap().unwrap(), msg);
+    assert_eq!(rx.next().await.unwrap().unwrap(), msg);
+}
+
+#[tokio::test]
+async fn encoded_frame_should_match_encoded_frame_on_wire() {
+    async fn wire<I>(item: I) -> Vec<u8>
+    where
+        AsyncProstWriter<tokio::io::DuplexStream, EventFrame, AsyncFlaggedFrameDestination>:
+            ProstWriterFor<I>,
+    {
+        let (a, mut b) = duplex(4096);
+        let mut tx = AsyncProstWriter::<_, EventFrame, _>::from(a).for_async_framed_with_flags();
+        tx.feed_item(item).await.unwrap();
+        tx.close().await.unwrap();
+        drop(tx);
+        let mut buf = Vec::new();
+        b.read_to_end(&mut buf).await.unwrap();
+        buf
+    }
+
+    let frame = EventFrame {
+        header: Some(Header { tag: 2 }),
+        body: Some(Either::Right(event())),
+        flags: FrameFlags::END_STREAM,
+        ..Default::default()
+    };
+    let encoded = EncodedFrame::new(&frame).unwrap();
+    assert_eq!(encoded.flags(), FrameFlags::END_STREAM);
+
+    let expected = wire(&frame).await;
+    assert_eq!(wire(encoded).await, expected);
+    assert_eq!(wire(frame).await, expected);
+}
+
+#[tokio::test]
+async fn encoded_message_should_be_batched() {
+    let (a, b) = duplex(4096);
+    let mut tx = AsyncProstWriter::<_, Event, _>::from(a)
+        .for_async_batched(4096, 3)
+        .unwrap();
+    let mut rx = AsyncProstReader::<_, Event, AsyncBatchDestination>::from(b);
+
+    let encoded = EncodedMessage::new(&event());
+    tx.feed_item(encoded.clone()).await.unwrap();
+    tx.feed(event()).await.unwrap();
+    tx.send_item(encoded).await.unwrap();
+    for _ in 0..3 {
+        assert_eq!(rx.next().await.unwrap().unwrap(), event());
+    }
+}
+
+#[tokio::test]
+async fn item_sink_should_forward_references_and_encoded_values() {
+    let (a, b) = duplex(64 * 1024);
+    let mut tx = AsyncProstWriter::<_, Event, _>::from(a).for_async();
+    let mut rx = AsyncProstReader::<_, Event, AsyncDestination>::from(b);
+
+    let encoded = EncodedMessage::new(&event());
+    let mut encoded = stream::iter([Ok(encoded.clone()), Ok(encoded)]);
+    tx.item_sink().send_all(&mut encoded).await.unwrap();
+    // `forward` closes the writer once the references are sent
+    let events = [event(), event()];
+    stream::iter(events.iter().map(Ok))
+        .forward(tx.item_sink())
+        .await
+        .unwrap();
+    for _ in 0..4 {
+        assert_eq!(rx.next().await.unwrap().unwrap(), event());
+    }
+
+    // a stream forwards encoded frames through its writing side
+    let (client, server) = AsyncProstStream::<_, EventFrame, EventFrame, _>::pair(64 * 1024);
+    let mut client = client.for_async_framed();
+    let mut server = server.for_async_framed();
+    let frame = EncodedFrame::new(&Frame::new(
+        Some(Header { tag: 1 }),
+        Some(Either::Right(event())),
+    ))
+    .unwrap();
+    stream::iter([Ok(frame)])
+        .forward(client.item_sink())
+        .await
+        .unwrap();
+    let got = server.next().await.unwrap().unwrap();
+    assert_eq!(got.header, Some(Header { tag: 1 }));
+}
@@ -0,0 +1,142 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures::prelude::*;
+use prost::Message;
+
+use async_prost::*;
+use tokio::io::{duplex, AsyncReadExt};
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Event {
+    #[prost(bytes = "bytes", tag = "1")]
+    pub data: Bytes,
+}
+
+fn event(i: u8) -> Event {
+    Event {
+        data: Bytes::from(vec![i; 10]),
+    }
+}
+
+#[tokio::test]
+async fn every_message_policy_should_flush_each_message() {
+    let (a, b) = duplex(4096);
+    let mut tx = AsyncProstWriter::<_, Event, _>::from(a)
+        .for_async()
+        .with_flush_policy(FlushPolicy::EveryMessage);
+    let mut rx = AsyncProstReader::<_, Event, AsyncDestination>::from(b);
+
+    // every message goes out as soon as it's fed, the last one included
+    for i in 0..5 {
+        tx.feed(event(i)).await.unwrap();
+        assert_eq!(tx.buffered(), 0);
+    }
+    for i in 0..5 {
+        assert_eq!(rx.next().await.unwrap().unwrap(), event(i));
+    }
+}
+
+#[tokio::test]
+async fn bytes_policy_should_flush_at_threshold() {
+    let (a, b) = duplex(4096);
+    let mut tx = AsyncProstWriter::<_, Event, _>::from(a)
+        .for_async()
+        .with_flush_policy(FlushPolicy::Bytes(64));
+    let mut rx = AsyncProstReader::<_, Event, AsyncDestination>::from(b);
+
+    let size = event(0).encoded_len() + 4;
+    assert_eq!(64 % size, 0);
+
+    // every 64 bytes go out as soon as they're buffered
+    for i in 0..22 {
+        tx.feed(event(i)).await.unwrap();
+        assert_eq!(tx.buffered(), (i as usize + 1) * size % 64);
+    }
+    tx.flush().await.unwrap();
+    for i in 0..22 {
+        assert_eq!(rx.next().await.unwrap().unwrap(), event(i));
+    }
+}
+
+#[tokio::test]
+async fn linger_policy_should_flush_after_timeout() {
+    let linger = Duration::from_millis(20);
+    let (a, b) = duplex(4096);
+    let mut tx = AsyncProstWriter::<_, Event, _>::from(a)
+        .for_async()
+        .with_flush_policy(FlushPolicy::Linger(linger));
+    let mut rx = AsyncProstReader::<_, Event, AsyncDestination>::from(b);
+
+    // a lone message goes out once it lingered, without another send
+    let start = tokio::time::Instant::now();
+    tx.feed(event(0)).await.unwrap();
+    assert!(tx.buffered() > 0);
+    tx.flush_when_due().await.unwrap();
+    assert!(start.elapsed() >= linger);
+    assert_eq!(tx.buffered(), 0);
+    assert_eq!(rx.next().await.unwrap().unwrap(), event(0));
+
+    // the timer is checked when the writer is polled for the next message as well
+    tx.feed(event(1)).await.unwrap();
+    tokio::time::sleep(linger).await;
+    tx.feed(event(2)).await.unwrap();
+    assert_eq!(tx.buffered(), event(2).encoded_len() + 4);
+    assert_eq!(rx.next().await.unwrap().unwrap(), event(1));
+}
+
+#[tokio::test]
+async fn flush_when_due_should_drive_a_send_loop() {
+    let (a, b) = duplex(4096);
+    let mut tx = AsyncProstWriter::<_, Event, _>::from(a)
+        .for_async()
+        .with_flush_policy(FlushPolicy::Linger(Duration::from_millis(10)));
+    let mut rx = AsyncProstReader::<_, Event, AsyncDestination>::from(b);
+    let (events, mut source) = tokio::sync::mpsc::channel(4);
+
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                event = source.recv() => match event {
+                    Some(event) => tx.feed(event).await.unwrap(),
+                    // keep the writer open, the flush must not rely on close
+                    None => std::future::pending().await,
+                },
+                res = tx.flush_when_due() => res.unwrap(),
+            }
+        }
+    });
+
+    for i in 0..3 {
+        events.send(event(i)).await.unwrap();
+    }
+    for i in 0..3 {
+        assert_eq!(rx.next().await.unwrap().unwrap(), event(i));
+    }
+}
+
+#[tokio::test]
+async fn flush_policy_should_not_change_wire_output() {
+    async fn wire(policy: FlushPolicy) -> Vec<u8> {
+        let (a, mut b) = duplex(4096);
+        let mut tx = AsyncProstWriter::<_, Event, _>::from(a)
+            .for_async()
+            .with_flush_policy(policy);
+        for i in 0..10 {
+            tx.feed(event(i)).await.unwrap();
+        }
+        tx.close().await.unwrap();
+        drop(tx);
+        let mut buf = Vec::new();
+        b.read_to_end(&mut buf).await.unwrap();
+        buf
+    }
+
+    let expected = wire(FlushPolicy::Manual).await;
+    assert_eq!(wire(FlushPolicy::EveryMessage).await, expected);
+    assert_eq!(wire(FlushPolicy::Bytes(32)).await, expected);
+    assert_eq!(
+        wire(FlushPolicy::Linger(Duration::from_millis(0))).await,
+        expected
+    );
+}
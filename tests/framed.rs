@@ -9,8 +9,8 @@ use bytes::Bytes;
 use prost::Message;
 
 use async_prost::*;
+use async_prost_derive::Framed;
 use slab::Slab;
-use tokio::net::{TcpListener, TcpStream};
 use tokio_tower::multiplex::{Client, MultiplexTransport, Server, TagStore};
 use tower::Service;
 
@@ -50,10 +50,10 @@ impl From<RequestFrame> for ResponseFrame {
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Framed)]
 struct RequestFrame(Frame<Header, Body>);
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Framed)]
 struct ResponseFrame(Frame<Header, Body>);
 
 impl RequestFrame {
@@ -61,6 +61,7 @@ impl RequestFrame {
         RequestFrame(Frame {
             header: Some(Header { tag: 0 }),
             body: Some(Either::Right(Body { data })),
+            ..Default::default()
         })
     }
 
@@ -93,54 +94,20 @@ impl ResponseFrame {
     }
 }
 
-impl Framed for RequestFrame {
-    fn decode(buf: &[u8], header_len: usize) -> Result<Self, std::io::Error> {
-        let frame = Frame::decode(buf, header_len)?;
-        Ok(Self(frame))
-    }
-
-    fn encoded_len(&self) -> u32
-    where
-        Self: Sized,
-    {
-        self.0.encoded_len()
-    }
-
-    fn encode<B>(&self, buf: &mut B) -> Result<(), std::io::Error>
-    where
-        B: bytes::BufMut,
-        Self: Sized,
-    {
-        self.0.encode(buf)
-    }
-}
-
-impl Framed for ResponseFrame {
-    fn decode(buf: &[u8], header_len: usize) -> Result<Self, std::io::Error> {
-        let frame = Frame::decode(buf, header_len)?;
-        Ok(Self(frame))
-    }
-
-    fn encoded_len(&self) -> u32
-    where
-        Self: Sized,
-    {
-        self.0.encoded_len()
-    }
-
-    fn encode<B>(&self, buf: &mut B) -> Result<(), std::io::Error>
-    where
-        B: bytes::BufMut,
-        Self: Sized,
-    {
-        self.0.encode(buf)
-    }
-}
-
 impl ResponseFrame {
     pub fn get_tag(&self) -> usize {
         self.0.header.as_ref().unwrap().tag as usize
     }
+
+    /// check the body, which is only decoded for even tags. the tags the client assigns depend on
+    /// how fast the answers come back, as a tag is reused once its call is answered
+    pub fn check(&self, expected: Bytes) {
+        if self.get_tag() % 2 == 1 {
+            self.check_body(expected)
+        } else {
+            self.check_data(expected)
+        }
+    }
 }
 
 pub struct EchoService;
@@ -176,18 +143,15 @@ impl TagStore<RequestFrame, ResponseFrame> for SlabStore {
 
 #[tokio::test]
 async fn framed_tokio_tower_should_work() {
-    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
-    let addr = listener.local_addr().unwrap();
+    let (tx, rx) = AsyncProstStream::pair(4096);
 
-    // connect
-    let tx = TcpStream::connect(&addr).await.unwrap();
-    let tx = AsyncProstStream::from(tx).for_async_framed();
+    // client
+    let tx = tx.for_async_framed();
     let mut tx: Client<_, PanicError, _> =
         Client::new(MultiplexTransport::new(tx, SlabStore(Slab::new())));
 
-    // accept
-    let (rx, _) = listener.accept().await.unwrap();
-    let rx = AsyncProstStream::from(rx).for_async_framed();
+    // server
+    let rx = rx.for_async_framed();
     let server = Server::new(rx, EchoService);
     tokio::spawn(async move { server.await.unwrap() });
 
@@ -203,7 +167,174 @@ async fn framed_tokio_tower_should_work() {
     let fut3 = tx.call(RequestFrame::new(b3.clone()));
     unwrap(ready(&mut tx).await);
 
-    unwrap(fut1.await).check_data(b1);
-    unwrap(fut2.await).check_body(b2);
-    unwrap(fut3.await).check_data(b3);
+    unwrap(fut1.await).check(b1);
+    unwrap(fut2.await).check(b2);
+    unwrap(fut3.await).check(b3);
+}
+
+/// a frame still implementing the legacy `encoded_len`
+#[derive(Debug, Default)]
+struct LegacyFrame(Frame<Header, Body>);
+
+impl LegacyFramed for LegacyFrame {
+    fn decode(buf: &[u8], header_len: usize) -> Result<Self, std::io::Error> {
+        let frame = Framed::decode(buf, header_len)?;
+        Ok(Self(frame))
+    }
+
+    fn encoded_len(&self) -> u32 {
+        (self.0.header_len().unwrap() as u32) << 24 | self.0.body_len().unwrap() as u32
+    }
+
+    fn encode<B>(&self, buf: &mut B) -> Result<(), std::io::Error>
+    where
+        B: bytes::BufMut,
+        Self: Sized,
+    {
+        Framed::encode(&self.0, buf)
+    }
+}
+
+#[test]
+fn legacy_encoded_len_should_still_work() {
+    let frame = LegacyFrame(Frame {
+        header: Some(Header { tag: 1 << 40 }),
+        body: Some(Either::Right(Body::new(Bytes::from_static(b"hello")))),
+        ..Default::default()
+    });
+    assert_eq!(frame.header_len().unwrap(), frame.0.header_len().unwrap());
+    assert_eq!(frame.body_len().unwrap(), frame.0.body_len().unwrap());
+}
+
+#[tokio::test]
+async fn oversized_frame_should_fail_to_send() {
+    use futures::SinkExt;
+
+    let (tx, _rx) = AsyncProstStream::<_, ResponseFrame, RequestFrame, _>::pair(4096);
+    let mut tx = tx.for_async_framed();
+
+    let mut frame = RequestFrame::new(Bytes::from(vec![0u8; MAX_BODY_LEN + 1]));
+    frame.set_tag(1);
+    let err = tx.send(frame).await.unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[tokio::test]
+async fn header_only_frames_with_flags_should_work() {
+    use futures::{SinkExt, StreamExt};
+
+    let (tx, rx) = AsyncProstStream::<_, RequestFrame, RequestFrame, _>::pair(4096);
+    let mut tx = tx.for_async_framed_with_flags();
+    let mut rx = rx.for_async_framed_with_flags();
+
+    // header-only control frame, and a frame with neither header nor body
+    for tag in [1, 0] {
+        let frame = RequestFrame(Frame {
+            header: Some(Header { tag }),
+            body: None,
+            flags: FrameFlags::CONTROL | FrameFlags::END_STREAM,
+            ..Default::default()
+        });
+        tx.send(frame).await.unwrap();
+        let got = rx.next().await.unwrap().unwrap();
+        assert_eq!(got.0.header.unwrap().tag, tag);
+        assert!(got.0.body.is_none());
+        assert!(got.0.flags.contains(FrameFlags::CONTROL));
+        assert!(got.0.flags.contains(FrameFlags::END_STREAM));
+        assert!(!got.0.flags.contains(FrameFlags::COMPRESSED));
+    }
+
+    let mut frame = RequestFrame::new(Bytes::from_static(b"hello"));
+    frame.0.flags = FrameFlags::PRIORITY;
+    tx.send(frame).await.unwrap();
+    let got = rx.next().await.unwrap().unwrap();
+    assert_eq!(got.0.flags, FrameFlags::PRIORITY);
+    ResponseFrame(got.0).check_data(Bytes::from_static(b"hello"));
+}
+
+#[tokio::test]
+async fn empty_bodies_should_be_told_from_header_only_frames() {
+    use futures::{SinkExt, StreamExt};
+
+    let (tx, rx) = AsyncProstStream::<_, RequestFrame, RequestFrame, _>::pair(4096);
+    let mut tx = tx.for_async_framed_with_flags();
+    let mut rx = rx.for_async_framed_with_flags();
+
+    // an empty body message is still a body with frame flags
+    let frame = Frame::new(
+        Some(Header { tag: 0 }),
+        Some(Either::Right(Body::default())),
+    );
+    tx.send(RequestFrame(frame)).await.unwrap();
+    let got = rx.next().await.unwrap().unwrap();
+    assert!(!got.0.flags.contains(FrameFlags::HEADER_ONLY));
+    assert_eq!(got.0.body, Some(Either::Right(Body::default())));
+
+    let frame = Frame::new(Some(Header { tag: 0 }), None);
+    tx.send(RequestFrame(frame)).await.unwrap();
+    let got = rx.next().await.unwrap().unwrap();
+    assert!(got.0.flags.contains(FrameFlags::HEADER_ONLY));
+    assert!(got.0.body.is_none());
+
+    // without frame flags, an empty body decodes as the default message, as it always did
+    let (tx, rx) = AsyncProstStream::<_, RequestFrame, RequestFrame, _>::pair(4096);
+    let mut tx = tx.for_async_framed();
+    let mut rx = rx.for_async_framed();
+    for tag in [0, 1] {
+        let frame = Frame::new(Some(Header { tag }), None);
+        tx.send(RequestFrame(frame)).await.unwrap();
+        let got = rx.next().await.unwrap().unwrap();
+        match got.0.body {
+            Some(Either::Right(v)) if tag == 0 => assert_eq!(v, Body::default()),
+            Some(Either::Left(v)) if tag == 1 => assert!(v.is_empty()),
+            body => panic!("unexpected body {:?}", body),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Status {
+    #[prost(uint32, tag = "1")]
+    pub code: u32,
+    #[prost(uint64, tag = "2")]
+    pub rows: u64,
+}
+
+#[tokio::test]
+async fn frame_trailers_should_work() {
+    use futures::{SinkExt, StreamExt};
+
+    type TrailerFrame = Frame<Header, Body, Status>;
+
+    let (tx, rx) = AsyncProstStream::<_, TrailerFrame, TrailerFrame, _>::pair(4096);
+    let mut tx = tx.for_async_framed();
+    let mut rx = rx.for_async_framed();
+
+    let status = Status { code: 200, rows: 3 };
+    let bodies = [Some(Body::new(Bytes::from_static(b"hello"))), None];
+    for body in bodies {
+        // an empty trailer is still a trailer
+        for trailer in [Some(status.clone()), Some(Status::default()), None] {
+            // odd tag keeps the body raw
+            for tag in [0, 1] {
+                let frame = TrailerFrame {
+                    header: Some(Header { tag }),
+                    body: body.clone().map(Either::Right),
+                    trailer: trailer.clone(),
+                    ..Default::default()
+                };
+                tx.send(frame).await.unwrap();
+                let got = rx.next().await.unwrap().unwrap();
+                assert_eq!(got.trailer, trailer);
+                match (got.body, body.as_ref()) {
+                    (Some(Either::Right(v)), Some(body)) => assert_eq!(&v, body),
+                    (Some(Either::Left(v)), Some(body)) => assert_eq!(v, body.encode_to_vec()),
+                    // without frame flags, a header-only frame is received with an empty body
+                    (Some(Either::Right(v)), None) => assert_eq!(v, Body::default()),
+                    (Some(Either::Left(v)), None) => assert!(v.is_empty()),
+                    _ => unreachable!(),
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,182 @@
+use bytes::Bytes;
+use either::Either;
+use futures::prelude::*;
+use prost::Message;
+
+use async_prost::*;
+use tokio::io::{duplex, AsyncWriteExt};
+
+mod common;
+use common::*;
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Header {
+    #[prost(uint32, tag = "1")]
+    pub tag: u32,
+}
+
+impl ShallDecodeBody for Header {
+    fn shall_decode_body(&self) -> bool {
+        true
+    }
+}
+
+type EventFrame = Frame<Header, Event>;
+
+#[tokio::test]
+async fn configured_framing_should_match_the_presets() {
+    // length prefix, read by a reader for the preset
+    let (a, b) = duplex(4096);
+    let mut tx = AsyncProstWriter::<_, Event, _>::from(a)
+        .for_configured(AsyncDestination.into())
+        .unwrap();
+    let mut rx = AsyncProstReader::<_, Event, AsyncDestination>::from(b);
+    tx.send(event(1)).await.unwrap();
+    assert_eq!(rx.next().await.unwrap().unwrap(), event(1));
+
+    // batches, written by a writer for the preset
+    let (a, b) = duplex(4096);
+    let mut tx = AsyncProstWriter::<_, Event, _>::from(a)
+        .for_async_batched(512, 2)
+        .unwrap();
+    let mut rx = AsyncProstReader::<_, Event, ConfiguredDestination>::from(b)
+        .with_framing(FramingConfig::new(Prefix::Batch));
+    for seq in 0..3 {
+        tx.feed(event(seq)).await.unwrap();
+    }
+    tx.flush().await.unwrap();
+    for seq in 0..3 {
+        assert_eq!(rx.next().await.unwrap().unwrap(), event(seq));
+    }
+}
+
+#[tokio::test]
+async fn configured_frames_should_carry_flags() {
+    let framing = FramingConfig::from(AsyncFlaggedFrameDestination);
+    let (a, b) = duplex(4096);
+    let mut client = AsyncProstStream::<_, EventFrame, EventFrame, _>::from(a)
+        .for_configured_framed(framing)
+        .unwrap();
+    let mut server =
+        AsyncProstStream::<_, EventFrame, EventFrame, _>::from(b).for_async_framed_with_flags();
+
+    let frame = EventFrame {
+        header: Some(Header { tag: 1 }),
+        body: Some(Either::Right(event(1))),
+        flags: FrameFlags::END_STREAM,
+        ..Default::default()
+    };
+    client.send(frame).await.unwrap();
+    let got = server.next().await.unwrap().unwrap();
+    assert_eq!(got.header, Some(Header { tag: 1 }));
+    assert_eq!(got.flags, FrameFlags::END_STREAM);
+
+    server.send(got).await.unwrap();
+    let got = client.next().await.unwrap().unwrap();
+    assert_eq!(got.flags, FrameFlags::END_STREAM);
+}
+
+#[tokio::test]
+async fn configured_framing_should_enforce_limits() {
+    let framing = FramingConfig::new(Prefix::Length).with_max_len(16);
+    let (a, mut b) = duplex(4096);
+    let mut tx = AsyncProstWriter::<_, Event, _>::from(a)
+        .for_configured(framing)
+        .unwrap();
+    let mut rx =
+        AsyncProstReader::<_, Event, ConfiguredDestination>::from(&mut b).with_framing(framing);
+
+    let large = Event {
+        seq: 1,
+        data: Bytes::from(vec![0; 64]),
+    };
+    let err = tx.send(large).await.unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    tx.send(event(2)).await.unwrap();
+    assert_eq!(rx.next().await.unwrap().unwrap(), event(2));
+
+    // an oversized prefix fails before the value is buffered
+    drop(rx);
+    let mut tx = tx.into_inner();
+    tx.write_all(&1024u32.to_be_bytes()).await.unwrap();
+    let mut rx = AsyncProstReader::<_, Event, ConfiguredDestination>::from(b).with_framing(framing);
+    let err = rx.next().await.unwrap().unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[tokio::test]
+async fn unsupported_prefix_should_fail() {
+    let (a, b) = duplex(4096);
+    let mut tx = AsyncProstWriter::<_, Event, _>::from(a)
+        .for_configured(FramingConfig::from(AsyncFrameDestination))
+        .unwrap();
+    let err = tx.send(event(1)).await.unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+
+    let mut rx = AsyncProstReader::<_, Event, ConfiguredDestination>::from(b)
+        .with_framing(FramingConfig::from(SyncDestination));
+    let err = rx.next().await.unwrap().unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[tokio::test]
+async fn invalid_limits_should_fail_to_configure() {
+    let invalid = [
+        FramingConfig::new(Prefix::Length).with_max_len(0),
+        FramingConfig::new(Prefix::Batch).with_batch_limits(512, 0),
+        FramingConfig::new(Prefix::Batch).with_batch_limits(0, 2),
+        FramingConfig::new(Prefix::Batch).with_batch_limits(u32::MAX as usize + 1, 2),
+    ];
+    for framing in invalid {
+        assert_eq!(
+            framing.validate().unwrap_err().kind(),
+            std::io::ErrorKind::InvalidInput
+        );
+        let (a, b) = duplex(4096);
+        let err = AsyncProstWriter::<_, Event, _>::from(a)
+            .for_configured(framing)
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        let err = AsyncProstStream::<_, Event, Event, _>::from(b)
+            .for_configured(framing)
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    // batch limits are only checked for batches
+    FramingConfig::new(Prefix::Length)
+        .with_batch_limits(512, 0)
+        .validate()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn presets_should_drop_the_configured_limits() {
+    let framing = FramingConfig::new(Prefix::Length).with_max_len(16);
+    let large = Event {
+        seq: 1,
+        data: Bytes::from(vec![0; 64]),
+    };
+
+    let (a, b) = duplex(4096);
+    let mut client = AsyncProstStream::<_, Event, Event, _>::from(a)
+        .for_configured(framing)
+        .unwrap()
+        .for_async();
+    let mut server = AsyncProstStream::<_, Event, Event, _>::from(b)
+        .for_configured(framing)
+        .unwrap()
+        .for_async();
+    client.send(large.clone()).await.unwrap();
+    assert_eq!(server.next().await.unwrap().unwrap(), large);
+    server.send(large.clone()).await.unwrap();
+    assert_eq!(client.next().await.unwrap().unwrap(), large);
+
+    // retyping keeps them
+    let mut client = client
+        .for_configured(framing)
+        .unwrap()
+        .retype::<Event, Event>();
+    let err = client.send(large).await.unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+}
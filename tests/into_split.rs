@@ -0,0 +1,87 @@
+use std::io;
+
+use futures::prelude::*;
+
+use async_prost::*;
+use tokio::net::{TcpListener, TcpStream};
+
+mod common;
+use common::*;
+
+#[tokio::test]
+async fn tcp_owned_halves_should_move_to_tasks() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    // the server echoes with its halves in different tasks
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let stream = AsyncProstStream::<_, Event, Event, _>::from(stream).for_async();
+        let (reader, writer) = stream.into_split();
+        let (tx, rx) = futures::channel::mpsc::unbounded();
+        tokio::spawn(
+            reader.forward(tx.sink_map_err(|e| io::Error::new(io::ErrorKind::BrokenPipe, e))),
+        );
+        tokio::spawn(rx.map(Ok).forward(writer));
+    });
+
+    let stream = TcpStream::connect(&addr).await.unwrap();
+    let mut client = AsyncProstStream::<_, Event, Event, _>::from(stream).for_async();
+    client.feed(event(1)).await.unwrap();
+    client.feed(event(2)).await.unwrap();
+    client.flush().await.unwrap();
+    assert_eq!(client.next().await.unwrap().unwrap(), event(1));
+
+    // the second event may already be buffered, it must not be lost
+    let (mut reader, mut writer) = client.into_split();
+    assert_eq!(reader.next().await.unwrap().unwrap(), event(2));
+    writer.send(event(3)).await.unwrap();
+    let got = tokio::spawn(async move { reader.next().await.unwrap().unwrap() });
+    assert_eq!(got.await.unwrap(), event(3));
+}
+
+#[tokio::test]
+async fn tcp_borrowed_halves_should_keep_buffered_state() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let tx = TcpStream::connect(&addr).await.unwrap();
+    let (rx, _) = listener.accept().await.unwrap();
+    let mut client = AsyncProstStream::<_, Event, Event, _>::from(tx).for_async();
+    let mut server = AsyncProstStream::<_, Event, Event, _>::from(rx).for_async();
+
+    client.feed(event(1)).await.unwrap();
+    client.feed(event(2)).await.unwrap();
+    client.flush().await.unwrap();
+    assert_eq!(server.next().await.unwrap().unwrap(), event(1));
+    server.feed(event(10)).await.unwrap();
+
+    // the halves borrow the stream, the buffered data goes with them
+    let (mut reader, mut writer) = server.tcp_split();
+    assert_eq!(reader.next().await.unwrap().unwrap(), event(2));
+    writer.flush().await.unwrap();
+    writer.send(event(11)).await.unwrap();
+    assert_eq!(client.next().await.unwrap().unwrap(), event(10));
+    assert_eq!(client.next().await.unwrap().unwrap(), event(11));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn unix_owned_halves_should_keep_buffered_state() {
+    use tokio::net::UnixStream;
+
+    let (a, b) = UnixStream::pair().unwrap();
+    let mut a = AsyncProstStream::<_, Event, Event, _>::from(a).for_async();
+    let mut b = AsyncProstStream::<_, Event, Event, _>::from(b).for_async();
+
+    b.feed(event(1)).await.unwrap();
+    b.feed(event(2)).await.unwrap();
+    b.flush().await.unwrap();
+    assert_eq!(a.next().await.unwrap().unwrap(), event(1));
+    a.feed(event(10)).await.unwrap();
+
+    let (mut reader, mut writer) = a.into_split();
+    let reading = tokio::spawn(async move { reader.next().await.unwrap().unwrap() });
+    assert_eq!(reading.await.unwrap(), event(2));
+    writer.flush().await.unwrap();
+    assert_eq!(b.next().await.unwrap().unwrap(), event(10));
+}
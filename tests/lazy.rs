@@ -0,0 +1,70 @@
+use bytes::Bytes;
+use either::Either;
+use futures::prelude::*;
+use prost::Message;
+
+use async_prost::*;
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Header {
+    #[prost(uint64, tag = "1")]
+    tag: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Body {
+    #[prost(bytes = "bytes", tag = "1")]
+    pub data: Bytes,
+}
+
+type LazyFrame = Frame<Header, LazyBody<Body>>;
+
+fn frame(tag: u64, data: &'static [u8]) -> LazyFrame {
+    Frame {
+        header: Some(Header { tag }),
+        body: Some(Either::Right(LazyBody::new(Body {
+            data: Bytes::from_static(data),
+        }))),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn lazy_body_should_only_decode_on_access() {
+    let (client, server) = AsyncProstStream::<_, LazyFrame, LazyFrame, _>::pair(4096);
+
+    // forward every frame back, touching the body only for odd tags
+    tokio::spawn(async move {
+        let mut stream = server.for_async_framed();
+        while let Some(mut frame) = stream.next().await.transpose().unwrap() {
+            let tag = frame.header.as_ref().unwrap().tag;
+            if let Some(Either::Right(body)) = frame.body.as_mut() {
+                assert!(!body.is_decoded());
+                assert!(body.raw().is_some());
+                if tag % 2 == 1 {
+                    body.get_mut().unwrap().data = Bytes::from_static(b"modified");
+                    assert!(body.raw().is_none());
+                }
+            }
+            stream.send(frame).await.unwrap();
+        }
+    });
+
+    let mut client = client.for_async_framed();
+
+    client.send(frame(0, b"hello")).await.unwrap();
+    let got = client.next().await.unwrap().unwrap();
+    let body = got.body.unwrap().right().unwrap();
+    assert_eq!(body.get().unwrap().data, Bytes::from_static(b"hello"));
+    assert!(body.is_decoded());
+    // reading doesn't invalidate the raw bytes
+    assert!(body.raw().is_some());
+
+    client.send(frame(1, b"hello")).await.unwrap();
+    let got = client.next().await.unwrap().unwrap();
+    let body = got.body.unwrap().right().unwrap();
+    assert_eq!(
+        body.into_inner().unwrap().data,
+        Bytes::from_static(b"modified")
+    );
+}
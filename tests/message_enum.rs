@@ -0,0 +1,125 @@
+use bytes::Bytes;
+use futures::prelude::*;
+use prost::Message;
+
+use async_prost::*;
+use async_prost_derive::MessageEnum;
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Login {
+    #[prost(string, tag = "1")]
+    pub name: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Data {
+    #[prost(bytes = "bytes", tag = "1")]
+    pub data: Bytes,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Logout {}
+
+#[derive(Debug, Clone, PartialEq, MessageEnum)]
+pub enum Command {
+    Login(Login),
+    Data(Data),
+    #[message_enum(tag = 10)]
+    Logout(Logout),
+}
+
+/// a protobuf oneof with the same tags, to check wire compatibility
+#[derive(Clone, PartialEq, Message)]
+pub struct OneofCommand {
+    #[prost(oneof = "one_of::Cmd", tags = "1, 2, 10")]
+    pub cmd: Option<one_of::Cmd>,
+}
+
+pub mod one_of {
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum Cmd {
+        #[prost(message, tag = "1")]
+        Login(super::Login),
+        #[prost(message, tag = "2")]
+        Data(super::Data),
+        #[prost(message, tag = "10")]
+        Logout(super::Logout),
+    }
+}
+
+#[test]
+fn message_enum_should_be_wire_compatible_with_oneof() {
+    let cmd = Command::Data(Data {
+        data: Bytes::from_static(b"hello"),
+    });
+    let buf = cmd.encode_to_vec();
+    let oneof = OneofCommand::decode(buf.as_slice()).unwrap();
+    assert_eq!(
+        oneof.cmd,
+        Some(one_of::Cmd::Data(Data {
+            data: Bytes::from_static(b"hello")
+        }))
+    );
+    assert_eq!(
+        Command::decode(oneof.encode_to_vec().as_slice()).unwrap(),
+        cmd
+    );
+
+    let buf = Command::Logout(Logout {}).encode_to_vec();
+    assert_eq!(
+        Command::decode(buf.as_slice()).unwrap(),
+        Command::Logout(Logout {})
+    );
+}
+
+#[test]
+fn message_enum_without_known_variant_should_fail() {
+    assert!(Command::decode(&[][..]).is_err());
+
+    // a field with an unknown tag only
+    let unknown = Data {
+        data: Bytes::from_static(b"hello"),
+    };
+    let mut buf = Vec::new();
+    prost::encoding::message::encode(7, &unknown, &mut buf);
+    assert!(Command::decode(buf.as_slice()).is_err());
+
+    // unknown fields next to a known one are skipped
+    Command::Logout(Logout {}).encode(&mut buf).unwrap();
+    assert_eq!(
+        Command::decode(buf.as_slice()).unwrap(),
+        Command::Logout(Logout {})
+    );
+
+    let buf = Command::Logout(Logout {}).encode_length_delimited_to_vec();
+    assert_eq!(
+        Command::decode_length_delimited(buf.as_slice()).unwrap(),
+        Command::Logout(Logout {})
+    );
+    assert!(Command::decode_length_delimited(&[0u8][..]).is_err());
+}
+
+#[tokio::test]
+async fn message_enum_stream_should_work() {
+    let (client, server) = AsyncProstStream::<_, Command, Command, _>::pair(4096);
+
+    tokio::spawn(async move {
+        let (r, w) = server.for_async().split();
+        r.forward(w).await.unwrap();
+    });
+
+    let mut client = client.for_async();
+    let commands = vec![
+        Command::Login(Login {
+            name: "tyr".to_string(),
+        }),
+        Command::Data(Data {
+            data: Bytes::from_static(b"hello world"),
+        }),
+        Command::Logout(Logout {}),
+    ];
+    for cmd in commands {
+        client.send(cmd.clone()).await.unwrap();
+        assert_eq!(client.next().await.unwrap().unwrap(), cmd);
+    }
+}
@@ -0,0 +1,132 @@
+use bytes::Bytes;
+use either::Either;
+use futures::prelude::*;
+use prost::Message;
+
+use async_prost::*;
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Body {
+    #[prost(bytes = "bytes", tag = "1")]
+    pub data: Bytes,
+}
+
+type MetadataFrame = Frame<Metadata, Body>;
+
+#[test]
+fn metadata_accessors_should_work() {
+    let mut metadata = Metadata::default();
+    metadata.set_request_id(42);
+    metadata.set_content_type("application/x-protobuf");
+    metadata.insert("retry", 3);
+    metadata.insert_bin("trace", b"\x01\x02".to_vec());
+
+    assert_eq!(metadata.request_id(), Some("42"));
+    assert_eq!(metadata.get_as::<u64>(REQUEST_ID).unwrap().unwrap(), 42);
+    assert!(metadata.get_as::<u64>(CONTENT_TYPE).unwrap().is_err());
+    assert_eq!(metadata.content_type(), Some("application/x-protobuf"));
+    assert_eq!(metadata.get_as::<u8>("retry").unwrap().unwrap(), 3);
+    assert_eq!(metadata.get_bin("trace"), Some(&b"\x01\x02"[..]));
+    assert_eq!(metadata.authorization(), None);
+
+    assert_eq!(metadata.remove("retry"), Some("3".to_string()));
+    assert!(metadata.get("retry").is_none());
+    assert!(metadata.shall_decode_body());
+}
+
+#[tokio::test]
+async fn metadata_frame_should_work() {
+    let (tx, rx) = AsyncProstStream::<_, MetadataFrame, MetadataFrame, _>::pair(4096);
+    let mut tx = tx.for_async_framed();
+    let mut rx = rx.for_async_framed();
+
+    let body = Body {
+        data: Bytes::from_static(b"hello"),
+    };
+    let mut frame = MetadataFrame {
+        body: Some(Either::Right(body.clone())),
+        ..Default::default()
+    };
+    frame.set_metadata(AUTHORIZATION, "token");
+    frame.set_metadata_bin("trace", b"abc".to_vec());
+    tx.send(frame).await.unwrap();
+
+    let got = rx.next().await.unwrap().unwrap();
+    assert_eq!(got.metadata(AUTHORIZATION), Some("token"));
+    assert_eq!(got.metadata_bin("trace"), Some(&b"abc"[..]));
+    assert_eq!(got.body.unwrap().right().unwrap(), body);
+
+    // raw body is kept undecoded
+    let mut frame = MetadataFrame {
+        body: Some(Either::Right(body.clone())),
+        ..Default::default()
+    };
+    frame.header.get_or_insert_with(Metadata::default).raw_body = true;
+    tx.send(frame).await.unwrap();
+    let got = rx.next().await.unwrap().unwrap();
+    assert_eq!(got.body.unwrap().left().unwrap(), body.encode_to_vec());
+}
+
+/// a JWT-like token of about 1 KiB, as issued by common identity providers
+fn jwt() -> String {
+    let claims = "eyJzdWIiOiIxMjM0NTY3ODkwIiwicm9sZXMiOlsiYWRtaW4iLCJ1c2VyIl19".repeat(14);
+    let signature = "SflKxwRJSMeKKF2QT4fwpMeJf36POk6yJV_adQssw5c".repeat(3);
+    format!(
+        "Bearer eyJhbGciOiJSUzI1NiIsInR5cCI6IkpXVCJ9.{}.{}",
+        claims, signature
+    )
+}
+
+#[tokio::test]
+async fn long_metadata_should_work_with_frame_flags() {
+    let token = jwt();
+    assert!(token.len() > 1000);
+    let mut frame = MetadataFrame {
+        body: Some(Either::Right(Body {
+            data: Bytes::from_static(b"hello"),
+        })),
+        ..Default::default()
+    };
+    frame
+        .header
+        .get_or_insert_with(Metadata::default)
+        .set_authorization(&token);
+    frame.set_metadata(REQUEST_ID, 42);
+    let encoded = EncodedFrame::new(&frame).unwrap();
+
+    let (tx, rx) = AsyncProstStream::<_, MetadataFrame, MetadataFrame, _>::pair(4096);
+    let mut tx = tx.for_async_framed_with_flags();
+    let mut rx = rx.for_async_framed_with_flags();
+    tx.send(frame).await.unwrap();
+    tx.send_item(encoded.clone()).await.unwrap();
+    for _ in 0..2 {
+        let got = rx.next().await.unwrap().unwrap();
+        assert_eq!(got.metadata(AUTHORIZATION), Some(token.as_str()));
+        assert_eq!(got.metadata(REQUEST_ID), Some("42"));
+        assert!(!got.flags.contains(FrameFlags::LONG_HEADER));
+        assert_eq!(got.body.unwrap().right().unwrap().data, &b"hello"[..]);
+    }
+
+    // without frame flags the header is limited to MAX_HEADER_LEN
+    let (tx, _rx) = AsyncProstStream::<_, MetadataFrame, MetadataFrame, _>::pair(4096);
+    let mut tx = tx.for_async_framed();
+    let err = tx.send_item(encoded).await.unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[tokio::test]
+async fn forged_long_header_should_fail_to_read() {
+    use tokio::io::AsyncWriteExt;
+
+    let (mut tx, rx) = tokio::io::duplex(4096);
+    let mut rx = AsyncProstReader::<_, MetadataFrame, AsyncFlaggedFrameDestination>::from(rx);
+
+    // no body, a long header of 4 GiB which is never sent
+    tx.write_all(&0u32.to_be_bytes()).await.unwrap();
+    tx.write_u8(FrameFlags::LONG_HEADER.bits()).await.unwrap();
+    tx.write_all(&u32::MAX.to_be_bytes()).await.unwrap();
+    // fails at once instead of waiting for the header
+    let next = tokio::time::timeout(std::time::Duration::from_secs(1), rx.next());
+    let err = next.await.unwrap().unwrap().unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+}
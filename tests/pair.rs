@@ -0,0 +1,148 @@
+use bytes::Bytes;
+use either::Either;
+use futures::prelude::*;
+use prost::Message;
+
+use async_prost::*;
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Request {
+    #[prost(uint64, tag = "1")]
+    pub id: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Response {
+    #[prost(uint64, tag = "1")]
+    pub id: u64,
+    #[prost(bytes = "bytes", tag = "2")]
+    pub data: Bytes,
+}
+
+fn response(id: u64) -> Response {
+    Response {
+        id,
+        data: Bytes::from_static(b"hello"),
+    }
+}
+
+#[tokio::test]
+async fn pair_should_be_connected() {
+    let (client, server) = AsyncProstStream::<_, Response, Request, _>::pair(4096);
+    let mut client = client.for_async();
+    let mut server = server.for_async();
+
+    client.send(Request { id: 1 }).await.unwrap();
+    let request = server.next().await.unwrap().unwrap();
+    assert_eq!(request, Request { id: 1 });
+    server.send(response(request.id)).await.unwrap();
+    assert_eq!(client.next().await.unwrap().unwrap(), response(1));
+
+    // closing one side ends the other
+    client.close().await.unwrap();
+    assert!(server.next().await.is_none());
+}
+
+#[tokio::test]
+async fn pair_should_carry_more_than_its_buffer() {
+    let (client, server) = AsyncProstStream::<_, Response, Request, _>::pair(16);
+    let mut client = client.for_async_batched(1024, 4).unwrap();
+    let mut server = server.for_async_batched(1024, 4).unwrap();
+
+    // far more than the buffer holds, the sender waits for the receiver
+    let n = 1000;
+    let sender = tokio::spawn(async move {
+        for id in 0..n {
+            server.feed(response(id)).await.unwrap();
+        }
+        server.close().await.unwrap();
+    });
+    for id in 0..n {
+        assert_eq!(client.next().await.unwrap().unwrap(), response(id));
+    }
+    assert!(client.next().await.is_none());
+    sender.await.unwrap();
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Header {
+    #[prost(uint64, tag = "1")]
+    pub id: u64,
+}
+
+impl ShallDecodeBody for Header {
+    fn shall_decode_body(&self) -> bool {
+        true
+    }
+}
+
+type ResponseFrame = Frame<Header, Response>;
+
+#[tokio::test]
+async fn pair_should_take_any_framing() {
+    // frames with flags
+    let (client, server) = AsyncProstStream::<_, ResponseFrame, ResponseFrame, _>::pair(4096);
+    let mut client = client.for_async_framed_with_flags();
+    let mut server = server.for_async_framed_with_flags();
+    let frame = Frame {
+        header: Some(Header { id: 1 }),
+        body: Some(Either::Right(response(1))),
+        flags: FrameFlags::END_STREAM,
+        ..Default::default()
+    };
+    server.send(frame).await.unwrap();
+    let got = client.next().await.unwrap().unwrap();
+    assert_eq!(got.header, Some(Header { id: 1 }));
+    assert_eq!(got.flags, FrameFlags::END_STREAM);
+
+    // framing chosen at runtime
+    let framing = FramingConfig::new(Prefix::Batch).with_batch_limits(512, 2);
+    let (client, server) = AsyncProstStream::<_, Response, Request, _>::pair(4096);
+    let mut client = client.for_configured(framing).unwrap();
+    let mut server = server.for_configured(framing).unwrap();
+    client.send(Request { id: 2 }).await.unwrap();
+    assert_eq!(server.next().await.unwrap().unwrap(), Request { id: 2 });
+    for id in 0..3 {
+        server.feed(response(id)).await.unwrap();
+    }
+    server.flush().await.unwrap();
+    for id in 0..3 {
+        assert_eq!(client.next().await.unwrap().unwrap(), response(id));
+    }
+}
+
+#[tokio::test]
+async fn pair_for_should_frame_both_streams() {
+    let (mut client, mut server) =
+        AsyncProstStream::<_, Response, Request, _>::pair_for::<AsyncDestination>(4096);
+    client.send(Request { id: 1 }).await.unwrap();
+    assert_eq!(server.next().await.unwrap().unwrap(), Request { id: 1 });
+    server.send(response(1)).await.unwrap();
+    assert_eq!(client.next().await.unwrap().unwrap(), response(1));
+
+    let (mut client, mut server) = AsyncProstStream::<_, ResponseFrame, ResponseFrame, _>::pair_for::<
+        AsyncFlaggedFrameDestination,
+    >(4096);
+    let frame = Frame {
+        header: Some(Header { id: 2 }),
+        body: None,
+        flags: FrameFlags::CONTROL,
+        ..Default::default()
+    };
+    server.send(frame).await.unwrap();
+    let got = client.next().await.unwrap().unwrap();
+    assert_eq!(got.header, Some(Header { id: 2 }));
+    assert!(got.flags.contains(FrameFlags::CONTROL));
+    assert!(got.body.is_none());
+
+    // batches with the default limits
+    let (mut client, mut server) =
+        AsyncProstStream::<_, Response, Request, _>::pair_for::<AsyncBatchDestination>(4096);
+    for id in 0..3 {
+        server.feed(response(id)).await.unwrap();
+    }
+    server.flush().await.unwrap();
+    for id in 0..3 {
+        assert_eq!(client.next().await.unwrap().unwrap(), response(id));
+    }
+}
@@ -0,0 +1,46 @@
+use futures::prelude::*;
+
+use async_prost::*;
+use tokio::io::duplex;
+
+mod common;
+use common::*;
+
+#[tokio::test]
+async fn stream_from_parts_should_work() {
+    // two one-way pipes, like the stdin and stdout of a child process
+    let (up_tx, up_rx) = duplex(4096);
+    let (down_tx, down_rx) = duplex(4096);
+    let mut client = AsyncProstStream::<_, Event, Event, _>::from_parts(down_rx, up_tx)
+        .for_async_batched(512, 8)
+        .unwrap();
+    let mut server = AsyncProstStream::<_, Event, Event, _>::from_parts(up_rx, down_tx)
+        .for_async_batched(512, 8)
+        .unwrap();
+
+    client.send(event(1)).await.unwrap();
+    let got = server.next().await.unwrap().unwrap();
+    assert_eq!(got, event(1));
+    server.send(event(2)).await.unwrap();
+    assert_eq!(client.next().await.unwrap().unwrap(), event(2));
+}
+
+#[tokio::test]
+async fn into_parts_should_keep_buffered_state() {
+    let (up_tx, up_rx) = duplex(4096);
+    let (down_tx, down_rx) = duplex(4096);
+    let mut client = AsyncProstStream::<_, Event, Event, _>::from_parts(down_rx, up_tx).for_async();
+    let mut server = AsyncProstStream::<_, Event, Event, _>::from_parts(up_rx, down_tx).for_async();
+
+    // both events arrive in the same read, the second one stays buffered
+    server.feed(event(1)).await.unwrap();
+    server.feed(event(2)).await.unwrap();
+    server.flush().await.unwrap();
+    assert_eq!(client.next().await.unwrap().unwrap(), event(1));
+    client.feed(event(10)).await.unwrap();
+
+    let (mut reader, mut writer) = client.into_parts();
+    assert_eq!(reader.next().await.unwrap().unwrap(), event(2));
+    writer.flush().await.unwrap();
+    assert_eq!(server.next().await.unwrap().unwrap(), event(10));
+}
@@ -0,0 +1,86 @@
+use std::time::Duration;
+
+use futures::prelude::*;
+use prost::Message;
+
+use async_prost::*;
+use tokio::{io::duplex, time::timeout};
+
+mod common;
+use common::*;
+
+#[tokio::test]
+async fn high_priority_frames_should_go_out_first() {
+    let (a, b) = duplex(4096);
+    let mut tx = AsyncProstWriter::<_, Event, _>::from(a)
+        .for_async()
+        .with_priorities();
+    let mut rx = AsyncProstReader::<_, Event, AsyncDestination>::from(b);
+
+    for seq in 0..3 {
+        tx.feed((Priority::Low, sized_event(seq, 10)))
+            .await
+            .unwrap();
+    }
+    tx.feed((Priority::Normal, sized_event(10, 10)))
+        .await
+        .unwrap();
+    tx.feed((Priority::High, sized_event(20, 10)))
+        .await
+        .unwrap();
+    tx.feed((Priority::High, sized_event(21, 10)))
+        .await
+        .unwrap();
+    let low_len: usize = (0..3)
+        .map(|seq| sized_event(seq, 10).encoded_len() + 4)
+        .sum();
+    assert_eq!(tx.queued(Priority::Low), low_len);
+    tx.flush().await.unwrap();
+    assert_eq!(tx.buffered(), 0);
+
+    for seq in [20, 21, 10, 0, 1, 2] {
+        assert_eq!(rx.next().await.unwrap().unwrap().seq, seq);
+    }
+}
+
+#[tokio::test]
+async fn high_priority_frames_should_overtake_queued_bulk() {
+    let (a, b) = duplex(1024);
+    let mut tx = AsyncProstWriter::<_, Event, _>::from(a)
+        .for_async()
+        .with_priorities();
+    let mut rx = AsyncProstReader::<_, Event, AsyncDestination>::from(b);
+
+    let n = 64;
+    for seq in 0..n {
+        tx.feed((Priority::Low, sized_event(seq, 1000)))
+            .await
+            .unwrap();
+    }
+    // the peer doesn't read yet, so only the first batch is in flight
+    assert!(timeout(Duration::from_millis(20), tx.flush())
+        .await
+        .is_err());
+    tx.feed((Priority::High, sized_event(1000, 1)))
+        .await
+        .unwrap();
+
+    let reader = tokio::spawn(async move {
+        let mut got = Vec::new();
+        for _ in 0..=n {
+            got.push(rx.next().await.unwrap().unwrap().seq);
+        }
+        got
+    });
+    tx.flush().await.unwrap();
+    let got = reader.await.unwrap();
+
+    let pos = got.iter().position(|&seq| seq == 1000).unwrap();
+    assert!(
+        pos > 0 && pos < n as usize / 2,
+        "high priority frame at {}",
+        pos
+    );
+    let lows: Vec<_> = got.into_iter().filter(|&seq| seq != 1000).collect();
+    assert_eq!(lows, (0..n).collect::<Vec<_>>());
+}
@@ -0,0 +1,119 @@
+#![cfg(feature = "reflect")]
+
+use either::Either;
+use futures::prelude::*;
+use prost::Message;
+use prost_reflect::{DescriptorPool, DynamicMessage, MessageDescriptor, Value};
+use prost_types::{
+    field_descriptor_proto::{Label, Type},
+    DescriptorProto, FieldDescriptorProto, FileDescriptorProto, FileDescriptorSet,
+};
+
+use async_prost::*;
+use tokio::io::duplex;
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Header {
+    #[prost(uint64, tag = "1")]
+    tag: u64,
+}
+
+impl ShallDecodeBody for Header {
+    fn shall_decode_body(&self) -> bool {
+        self.tag % 2 == 0
+    }
+}
+
+/// the prost version of the runtime known `test.Event`
+#[derive(Clone, PartialEq, Message)]
+pub struct Event {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(uint64, tag = "2")]
+    pub seq: u64,
+}
+
+fn event_descriptor() -> MessageDescriptor {
+    let field = |name: &str, number, ty: Type| FieldDescriptorProto {
+        name: Some(name.to_string()),
+        number: Some(number),
+        label: Some(Label::Optional as i32),
+        r#type: Some(ty as i32),
+        ..Default::default()
+    };
+    let file = FileDescriptorProto {
+        name: Some("test.proto".to_string()),
+        package: Some("test".to_string()),
+        message_type: vec![DescriptorProto {
+            name: Some("Event".to_string()),
+            field: vec![
+                field("name", 1, Type::String),
+                field("seq", 2, Type::Uint64),
+            ],
+            ..Default::default()
+        }],
+        syntax: Some("proto3".to_string()),
+        ..Default::default()
+    };
+    let pool =
+        DescriptorPool::from_file_descriptor_set(FileDescriptorSet { file: vec![file] }).unwrap();
+    pool.get_message_by_name("test.Event").unwrap()
+}
+
+fn dynamic_event(desc: &MessageDescriptor, name: &str, seq: u64) -> DynamicMessage {
+    let mut msg = DynamicMessage::new(desc.clone());
+    msg.set_field_by_name("name", Value::String(name.to_string()));
+    msg.set_field_by_name("seq", Value::U64(seq));
+    msg
+}
+
+#[tokio::test]
+async fn dynamic_messages_should_work() {
+    let desc = event_descriptor();
+    let (tx, rx) = duplex(4096);
+    let mut tx = AsyncProstWriter::<_, DynamicMessage, _>::from(tx).for_async();
+    let mut rx = AsyncProstReader::<_, DynamicMessage, AsyncDestination>::from(rx)
+        .with_descriptor(desc.clone());
+
+    let msg = dynamic_event(&desc, "hello", 42);
+    tx.send(msg.clone()).await.unwrap();
+    let got = rx.next().await.unwrap().unwrap();
+    assert_eq!(got, msg);
+
+    // wire compatible with the generated message
+    let event = Event::decode(got.encode_to_vec().as_slice()).unwrap();
+    assert_eq!(event.name, "hello");
+    assert_eq!(event.seq, 42);
+}
+
+#[tokio::test]
+async fn dynamic_frame_body_should_work() {
+    type DynamicFrame = Frame<Header, DynamicBody>;
+
+    let desc = event_descriptor();
+    let (tx, rx) = duplex(4096);
+    let mut tx = AsyncProstWriter::<_, DynamicFrame, _>::from(tx).for_async_framed();
+    let mut rx = AsyncProstReader::<_, DynamicFrame, AsyncFrameDestination>::from(rx)
+        .with_descriptor(desc.clone());
+
+    for tag in [0, 1] {
+        let msg = dynamic_event(&desc, "frame", tag + 1);
+        let frame = DynamicFrame {
+            header: Some(Header { tag }),
+            body: Some(Either::Right(msg.clone().into())),
+            ..Default::default()
+        };
+        tx.send(frame).await.unwrap();
+        let got = rx.next().await.unwrap().unwrap();
+        match got.body.unwrap() {
+            Either::Right(body) => {
+                assert_eq!(tag, 0);
+                assert_eq!(body.0, msg);
+            }
+            Either::Left(raw) => {
+                assert_eq!(tag, 1);
+                assert_eq!(raw, msg.encode_to_vec());
+            }
+        }
+    }
+}
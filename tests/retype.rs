@@ -0,0 +1,67 @@
+use futures::prelude::*;
+use prost::Message;
+
+use async_prost::*;
+use tokio::io::duplex;
+
+mod common;
+use common::*;
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Hello {
+    #[prost(string, tag = "1")]
+    pub version: String,
+}
+
+fn hello() -> Hello {
+    Hello {
+        version: "1.0".into(),
+    }
+}
+
+#[tokio::test]
+async fn retype_should_keep_buffered_data() {
+    let (a, b) = duplex(4096);
+    let mut client = AsyncProstStream::<_, Hello, Hello, _>::from(a).for_async();
+    let mut server = AsyncProstStream::<_, Hello, Hello, _>::from(b).for_async();
+
+    // the client sends its first event right after the handshake, in the same write
+    client.feed(hello()).await.unwrap();
+    let mut client = client.retype::<Event, Event>();
+    client.feed(event(1)).await.unwrap();
+    client.flush().await.unwrap();
+
+    // the event is buffered by the server while it reads the handshake
+    assert_eq!(server.next().await.unwrap().unwrap(), hello());
+    let mut server = server.retype::<Event, Event>();
+    assert_eq!(server.next().await.unwrap().unwrap(), event(1));
+
+    server.send(event(2)).await.unwrap();
+    assert_eq!(client.next().await.unwrap().unwrap(), event(2));
+}
+
+#[tokio::test]
+async fn mode_switch_should_keep_buffered_data() {
+    let (a, b) = duplex(4096);
+    let mut client = AsyncProstStream::<_, Event, Event, _>::from(a)
+        .for_async_batched(512, 8)
+        .unwrap();
+    let mut server = AsyncProstStream::<_, Event, Event, _>::from(b)
+        .for_async_batched(512, 8)
+        .unwrap();
+
+    // the batch still open is sent as a batch, before the events sent after the switch
+    client.feed(event(1)).await.unwrap();
+    client.feed(event(2)).await.unwrap();
+    let mut client = client.for_async();
+    client.feed(event(3)).await.unwrap();
+    client.flush().await.unwrap();
+
+    assert_eq!(server.next().await.unwrap().unwrap(), event(1));
+    assert_eq!(server.next().await.unwrap().unwrap(), event(2));
+    let mut server = server.for_async();
+    assert_eq!(server.next().await.unwrap().unwrap(), event(3));
+
+    server.send(event(4)).await.unwrap();
+    assert_eq!(client.next().await.unwrap().unwrap(), event(4));
+}
@@ -9,7 +9,6 @@ use prost::Message;
 use async_prost::*;
 use futures_util::future::poll_fn;
 use slab::Slab;
-use tokio::net::{TcpListener, TcpStream};
 use tokio_tower::multiplex::{Client, MultiplexTransport, Server, TagStore};
 use tower::Service;
 
@@ -105,18 +104,15 @@ impl TagStore<Request, Response> for SlabStore {
 
 #[tokio::test]
 async fn tokio_tower_should_work() {
-    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
-    let addr = listener.local_addr().unwrap();
+    let (tx, rx) = AsyncProstStream::pair(4096);
 
-    // connect
-    let tx = TcpStream::connect(&addr).await.unwrap();
-    let tx = AsyncProstStream::from(tx).for_async();
+    // client
+    let tx = tx.for_async();
     let mut tx: Client<_, PanicError, _> =
         Client::new(MultiplexTransport::new(tx, SlabStore(Slab::new())));
 
-    // accept
-    let (rx, _) = listener.accept().await.unwrap();
-    let rx = AsyncProstStream::from(rx).for_async();
+    // server
+    let rx = rx.for_async();
     let server = Server::new(rx, EchoService);
     tokio::spawn(async move { server.await.unwrap() });
 
@@ -0,0 +1,56 @@
+use futures::prelude::*;
+
+use async_prost::*;
+use tokio::io::duplex;
+
+mod common;
+use common::*;
+
+#[tokio::test]
+async fn split_should_keep_buffered_state() {
+    let (a, b) = duplex(4096);
+    let mut stream = AsyncProstStream::<_, Event, Event, _>::from(a).for_async();
+    let mut peer = AsyncProstStream::<_, Event, Event, _>::from(b).for_async();
+
+    // both events arrive in the same read, the second one stays buffered
+    peer.feed(event(1)).await.unwrap();
+    peer.feed(event(2)).await.unwrap();
+    peer.flush().await.unwrap();
+    assert_eq!(stream.next().await.unwrap().unwrap(), event(1));
+
+    // an event buffered but not sent yet
+    stream.feed(event(10)).await.unwrap();
+
+    let (mut reader, mut writer) = stream.split();
+    assert_eq!(reader.next().await.unwrap().unwrap(), event(2));
+    writer.flush().await.unwrap();
+    assert_eq!(peer.next().await.unwrap().unwrap(), event(10));
+
+    // the halves can be used from different tasks
+    let reading = tokio::spawn(async move {
+        let got = reader.next().await.unwrap().unwrap();
+        (reader, got)
+    });
+    peer.send(event(3)).await.unwrap();
+    let (reader, got) = reading.await.unwrap();
+    assert_eq!(got, event(3));
+
+    let mut stream = AsyncProstStream::reunite(reader, writer).unwrap();
+    stream.send(event(11)).await.unwrap();
+    assert_eq!(peer.next().await.unwrap().unwrap(), event(11));
+}
+
+#[tokio::test]
+async fn reunite_should_fail_with_halves_of_different_streams() {
+    let (a, _) = duplex(64);
+    let (b, _) = duplex(64);
+    let (reader, _) = AsyncProstStream::<_, Event, Event, _>::from(a)
+        .for_async()
+        .split();
+    let (_, writer) = AsyncProstStream::<_, Event, Event, _>::from(b)
+        .for_async()
+        .split();
+
+    let err = AsyncProstStream::reunite(reader, writer).unwrap_err();
+    assert!(!err.0.get_ref().is_pair_of(err.1.get_ref()));
+}
@@ -0,0 +1,111 @@
+use std::{io, time::Duration};
+
+use bytes::Bytes;
+use futures::prelude::*;
+use prost::Message;
+
+use async_prost::*;
+use tokio::{
+    io::{duplex, AsyncReadExt},
+    time::sleep,
+};
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Event {
+    #[prost(bytes = "bytes", tag = "1")]
+    pub data: Bytes,
+}
+
+fn event() -> Event {
+    Event {
+        data: Bytes::from(vec![3u8; 1000]),
+    }
+}
+
+#[tokio::test]
+async fn flush_should_time_out_when_peer_stops_reading() {
+    let (a, b) = duplex(64);
+    let mut tx = AsyncProstWriter::<_, Event, _>::from(a)
+        .for_async()
+        .with_write_timeout(Duration::from_millis(50));
+    let mut rx = AsyncProstReader::<_, Event, AsyncDestination>::from(b);
+
+    tx.feed(event()).await.unwrap();
+    let err = tx.flush().await.unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+    let timeout = err.get_ref().unwrap().downcast_ref::<WriteTimeout>();
+    assert_eq!(timeout, Some(&WriteTimeout(Duration::from_millis(50))));
+
+    // the rest stays buffered, flushing again resumes
+    assert!(tx.buffered() > 0);
+    let reader = tokio::spawn(async move { rx.next().await.unwrap().unwrap() });
+    tx.flush().await.unwrap();
+    assert_eq!(reader.await.unwrap(), event());
+}
+
+#[tokio::test]
+async fn write_timeout_should_restart_on_progress() {
+    let (a, mut b) = duplex(64);
+    let mut tx = AsyncProstWriter::<_, Event, _>::from(a)
+        .for_async()
+        .with_write_timeout(Duration::from_millis(100));
+
+    // a slow reader, which takes much longer than the timeout in total
+    let reader = tokio::spawn(async move {
+        let mut buf = vec![0u8; 256];
+        let mut total = 0;
+        while let Ok(n) = b.read(&mut buf).await {
+            if n == 0 {
+                break;
+            }
+            total += n;
+            sleep(Duration::from_millis(20)).await;
+        }
+        total
+    });
+
+    for _ in 0..3 {
+        tx.feed(event()).await.unwrap();
+    }
+    tx.close().await.unwrap();
+    drop(tx);
+    assert_eq!(reader.await.unwrap(), 3 * (event().encoded_len() + 4));
+}
+
+#[tokio::test]
+async fn stream_close_should_time_out() {
+    let (a, _b) = duplex(64);
+    let mut stream = AsyncProstStream::<_, Event, Event, _>::from(a)
+        .for_async()
+        .with_write_timeout(Duration::from_millis(50));
+
+    stream.feed(event()).await.unwrap();
+    let err = stream.close().await.unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+}
+
+#[tokio::test]
+async fn dropped_flush_should_not_leave_its_deadline_behind() {
+    let (a, b) = duplex(64);
+    let mut tx = AsyncProstWriter::<_, Event, _>::from(a)
+        .for_async()
+        .with_write_timeout(Duration::from_millis(100));
+    let mut rx = AsyncProstReader::<_, Event, AsyncDestination>::from(b);
+
+    // give up on a flush before it times out, and wait past its deadline
+    tx.feed(event()).await.unwrap();
+    let flush = tokio::time::timeout(Duration::from_millis(30), tx.flush());
+    assert!(flush.await.is_err());
+    sleep(Duration::from_millis(150)).await;
+
+    // the peer reads again a bit later, well within the timeout of the next flush
+    let reader = tokio::spawn(async move {
+        sleep(Duration::from_millis(20)).await;
+        for _ in 0..2 {
+            assert_eq!(rx.next().await.unwrap().unwrap(), event());
+        }
+    });
+    tx.feed(event()).await.unwrap();
+    tx.flush().await.unwrap();
+    reader.await.unwrap();
+}
@@ -0,0 +1,112 @@
+use std::sync::OnceLock;
+
+use bytes::{BufMut, Bytes};
+use either::Either;
+use futures::prelude::*;
+use prost::{EncodeError, Message};
+
+use async_prost::*;
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Header {
+    #[prost(uint32, tag = "1")]
+    kind: u32,
+}
+
+impl MessageType for Header {
+    type Id = u32;
+
+    fn message_type(&self) -> u32 {
+        self.kind
+    }
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Ping {
+    #[prost(uint64, tag = "1")]
+    pub seq: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Data {
+    #[prost(bytes = "bytes", tag = "1")]
+    pub data: Bytes,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Body {
+    Ping(Ping),
+    Data(Data),
+}
+
+impl RegisteredBody for Body {
+    type Id = u32;
+
+    fn registry() -> &'static BodyRegistry<u32, Self> {
+        static REGISTRY: OnceLock<BodyRegistry<u32, Body>> = OnceLock::new();
+        REGISTRY.get_or_init(|| {
+            BodyRegistry::new()
+                .register(1, Body::Ping)
+                .register(2, Body::Data)
+        })
+    }
+
+    fn encoded_len(&self) -> usize {
+        match self {
+            Body::Ping(v) => v.encoded_len(),
+            Body::Data(v) => v.encoded_len(),
+        }
+    }
+
+    fn encode(&self, mut buf: &mut dyn BufMut) -> Result<(), EncodeError> {
+        match self {
+            Body::Ping(v) => v.encode(&mut buf),
+            Body::Data(v) => v.encode(&mut buf),
+        }
+    }
+}
+
+type MyFrame = TypedFrame<Header, Body>;
+
+#[tokio::test]
+async fn typed_frame_should_dispatch_by_header() {
+    let (client, server) = AsyncProstStream::<_, MyFrame, MyFrame, _>::pair(4096);
+
+    tokio::spawn(async move {
+        let (r, w) = server.for_async_framed().split();
+        r.forward(w).await.unwrap();
+    });
+
+    let mut client = client.for_async_framed();
+
+    let ping = Body::Ping(Ping { seq: 42 });
+    let data = Body::Data(Data {
+        data: Bytes::from_static(b"hello"),
+    });
+    for (kind, body) in [(1, ping), (2, data)] {
+        let frame = TypedFrame {
+            header: Some(Header { kind }),
+            body: Some(Either::Right(body)),
+            ..Default::default()
+        };
+        client.send(frame).await.unwrap();
+        let got = client.next().await.unwrap().unwrap();
+        assert_eq!(got.header.unwrap().kind, kind);
+        let body = got.body.unwrap().right().unwrap();
+        match (kind, body) {
+            (1, Body::Ping(v)) => assert_eq!(v.seq, 42),
+            (2, Body::Data(v)) => assert_eq!(v.data, Bytes::from_static(b"hello")),
+            _ => unreachable!(),
+        }
+    }
+
+    // unknown message ids are surfaced as raw bytes
+    let frame = MyFrame {
+        header: Some(Header { kind: 3 }),
+        body: Some(Either::Left(b"raw".to_vec())),
+        ..Default::default()
+    };
+    client.send(frame).await.unwrap();
+    let got = client.next().await.unwrap().unwrap();
+    assert_eq!(got.body.unwrap().left().unwrap(), b"raw".to_vec());
+}
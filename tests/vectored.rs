@@ -0,0 +1,173 @@
+use std::{
+    io::{self, IoSlice},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use bytes::{BufMut, Bytes};
+use either::Either;
+use futures::prelude::*;
+use prost::Message;
+
+use async_prost::*;
+use tokio::io::AsyncWrite;
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Header {
+    #[prost(uint64, tag = "1")]
+    tag: u64,
+}
+
+impl ShallDecodeBody for Header {
+    fn shall_decode_body(&self) -> bool {
+        true
+    }
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Body {
+    #[prost(bytes = "bytes", tag = "1")]
+    pub data: Bytes,
+}
+
+type RawFrame = Frame<Header, Body>;
+
+/// a writer that records the written bytes, the number of slices of each vectored write and
+/// the address of each slice
+#[derive(Debug, Default)]
+struct Recorder {
+    data: Vec<u8>,
+    vectored_writes: Vec<usize>,
+    addresses: Vec<usize>,
+    vectored: bool,
+}
+
+impl AsyncWrite for Recorder {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.data.extend_from_slice(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        self.vectored_writes.push(bufs.len());
+        let mut n = 0;
+        for buf in bufs {
+            self.addresses.push(buf.as_ptr() as usize);
+            self.data.extend_from_slice(buf);
+            n += buf.len();
+        }
+        Poll::Ready(Ok(n))
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.vectored
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+fn frames() -> Vec<RawFrame> {
+    (0..4u8)
+        .map(|i| Frame {
+            header: Some(Header { tag: i as u64 }),
+            body: Some(Either::Left(
+                Body {
+                    data: Bytes::from(vec![i; 1024 * (i as usize + 1)]),
+                }
+                .encode_to_vec(),
+            )),
+            ..Default::default()
+        })
+        .collect()
+}
+
+async fn write_all(vectored: bool) -> Recorder {
+    let recorder = Recorder {
+        vectored,
+        ..Default::default()
+    };
+    let mut tx = AsyncProstWriter::<_, RawFrame, _>::from(recorder).for_async_framed();
+    for frame in frames() {
+        tx.feed(frame).await.unwrap();
+    }
+    tx.flush().await.unwrap();
+    tx.into_inner()
+}
+
+#[tokio::test]
+async fn raw_bodies_should_be_written_vectored() {
+    let vectored = write_all(true).await;
+    let plain = write_all(false).await;
+
+    // prefix and header, then the referenced body, for each frame
+    assert_eq!(vectored.vectored_writes, vec![8]);
+    assert!(plain.vectored_writes.is_empty());
+    assert_eq!(vectored.data, plain.data);
+
+    let mut rx = AsyncProstReader::<_, RawFrame, AsyncFrameDestination>::from(&vectored.data[..]);
+    for frame in frames() {
+        let got = rx.next().await.unwrap().unwrap();
+        assert_eq!(got.header, frame.header);
+        let raw = frame.body.unwrap().left().unwrap();
+        assert_eq!(got.body.unwrap().right().unwrap().encode_to_vec(), raw);
+    }
+}
+
+#[test]
+fn write_buf_should_count_inline_and_referenced_payloads() {
+    let mut buf = WriteBuf::new();
+    buf.put_u32(1);
+    buf.put_bytes(Bytes::from_static(b"small"));
+    buf.put_bytes(Bytes::from(vec![0u8; INLINE_LIMIT]));
+    buf.put_slice(b"tail");
+    assert_eq!(buf.len(), 4 + 5 + INLINE_LIMIT + 4);
+    assert!(!buf.is_empty());
+}
+
+#[tokio::test]
+async fn bytes_fields_should_be_written_by_reference() {
+    let data = Bytes::from(vec![7u8; 4096]);
+    let recorder = Recorder {
+        vectored: true,
+        ..Default::default()
+    };
+    let mut tx = AsyncProstWriter::<_, Body, _>::from(recorder).for_async();
+    tx.send(Body { data: data.clone() }).await.unwrap();
+    let recorder = tx.into_inner();
+
+    // prefix and field key, then the payload itself rather than a copy
+    assert_eq!(recorder.vectored_writes, vec![2]);
+    assert_eq!(recorder.addresses[1], data.as_ptr() as usize);
+
+    let recorder = Recorder {
+        vectored: true,
+        ..Default::default()
+    };
+    let mut tx = AsyncProstWriter::<_, RawFrame, _>::from(recorder).for_async_framed();
+    let frame = Frame::new(
+        Some(Header { tag: 1 }),
+        Some(Either::Right(Body { data: data.clone() })),
+    );
+    tx.send(frame).await.unwrap();
+    let recorder = tx.into_inner();
+    assert_eq!(recorder.vectored_writes, vec![2]);
+    assert_eq!(recorder.addresses[1], data.as_ptr() as usize);
+
+    let mut rx = AsyncProstReader::<_, RawFrame, AsyncFrameDestination>::from(&recorder.data[..]);
+    let got = rx.next().await.unwrap().unwrap();
+    assert_eq!(got.body.unwrap().right().unwrap().data, data);
+}
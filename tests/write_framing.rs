@@ -0,0 +1,125 @@
+use either::Either;
+use futures::prelude::*;
+use prost::Message;
+
+use async_prost::*;
+use tokio::io::duplex;
+
+mod common;
+use common::*;
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Header {
+    #[prost(uint32, tag = "1")]
+    pub tag: u32,
+}
+
+impl ShallDecodeBody for Header {
+    fn shall_decode_body(&self) -> bool {
+        true
+    }
+}
+
+type EventFrame = Frame<Header, Event>;
+
+fn frame(event: Event) -> EventFrame {
+    EventFrame {
+        header: Some(Header {
+            tag: event.seq as u32,
+        }),
+        body: Some(Either::Right(event)),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn read_and_write_framing_should_be_independent() {
+    let (a, b) = duplex(4096);
+    // the client sends plain messages and gets frames back
+    let mut client = AsyncProstStream::<_, EventFrame, Event, _>::from(a)
+        .for_async_framed()
+        .with_write_framing(|w| Ok(w.for_async()))
+        .unwrap();
+    // the gateway does the opposite
+    let mut gateway = AsyncProstStream::<_, Event, EventFrame, _>::from(b)
+        .for_async()
+        .with_write_framing(|w| Ok(w.for_async_framed()))
+        .unwrap();
+
+    for seq in 0..3 {
+        client.feed(event(seq)).await.unwrap();
+    }
+    client.flush().await.unwrap();
+    for seq in 0..3 {
+        let request = gateway.next().await.unwrap().unwrap();
+        assert_eq!(request, event(seq));
+        gateway.feed(frame(request)).await.unwrap();
+    }
+    gateway.flush().await.unwrap();
+    for seq in 0..3 {
+        let response = client.next().await.unwrap().unwrap();
+        assert_eq!(response.header, Some(Header { tag: seq as u32 }));
+    }
+
+    // the halves keep their own framing
+    let (mut reader, mut writer) = gateway.split();
+    client.send(event(3)).await.unwrap();
+    let request = reader.next().await.unwrap().unwrap();
+    writer.send(frame(request)).await.unwrap();
+    let response = client.next().await.unwrap().unwrap();
+    assert_eq!(response.header, Some(Header { tag: 3 }));
+}
+
+#[tokio::test]
+async fn write_framing_switch_should_keep_buffered_data() {
+    let (a, b) = duplex(4096);
+    let mut client = AsyncProstStream::<_, Event, Event, _>::from(a).for_async();
+    let mut server = AsyncProstStream::<_, Event, Event, _>::from(b).for_async();
+
+    // the event still buffered is sent as it was framed
+    client.feed(event(1)).await.unwrap();
+    let mut client = client
+        .with_write_framing(|w| w.for_async_batched(512, 8))
+        .unwrap();
+    client.feed(event(2)).await.unwrap();
+    client.flush().await.unwrap();
+
+    assert_eq!(server.next().await.unwrap().unwrap(), event(1));
+    let mut server = server
+        .for_async_batched(512, 8)
+        .unwrap()
+        .with_write_framing(|w| Ok(w.for_async()))
+        .unwrap();
+    assert_eq!(server.next().await.unwrap().unwrap(), event(2));
+
+    server.send(event(3)).await.unwrap();
+    assert_eq!(client.next().await.unwrap().unwrap(), event(3));
+}
+
+#[tokio::test]
+async fn write_framing_should_be_configurable_at_runtime() {
+    let (a, b) = duplex(4096);
+    let mut client = AsyncProstStream::<_, EventFrame, Event, _>::from(a)
+        .for_async_framed()
+        .with_write_framing(|w| Ok(w.for_async()))
+        .unwrap();
+    let framing = FramingConfig::from(AsyncFrameDestination);
+    let mut gateway = AsyncProstStream::<_, Event, EventFrame, _>::from(b)
+        .for_async()
+        .with_write_framing(|w| w.for_configured_framed(framing))
+        .unwrap();
+
+    client.send(event(1)).await.unwrap();
+    let request = gateway.next().await.unwrap().unwrap();
+    gateway.send(frame(request)).await.unwrap();
+    let response = client.next().await.unwrap().unwrap();
+    assert_eq!(response.header, Some(Header { tag: 1 }));
+
+    // an invalid framing is an error, not a panic
+    let (a, _b) = duplex(4096);
+    let err = AsyncProstStream::<_, Event, Event, _>::from(a)
+        .for_async()
+        .with_write_framing(|w| w.for_configured(FramingConfig::default().with_max_len(0)))
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}